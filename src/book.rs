@@ -0,0 +1,103 @@
+use crate::slugify;
+
+/// One chapter of a book-mode page, split out at a top-level heading
+pub struct Chapter<'a> {
+    pub title: String,
+    pub slug: String,
+    /// Markdown for the chapter, starting with its heading
+    pub body: &'a str,
+}
+
+/// Split a markdown body at each top-level (`# `) heading, returning the text before
+/// the first heading along with the chapters. Headings inside fenced code blocks are
+/// ignored. Chapter slugs are derived from the heading, and kept unique within the book.
+pub fn split_chapters(body: &str) -> (&str, Vec<Chapter<'_>>) {
+    let mut starts = vec![];
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None if line.starts_with("# ") => starts.push(offset),
+            None => {}
+        }
+
+        offset += line.len();
+    }
+
+    let preamble = &body[..starts.first().copied().unwrap_or(body.len())];
+
+    let mut chapters: Vec<Chapter> = vec![];
+
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(body.len());
+        let body = &body[start..end];
+
+        let title = body
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('#')
+            .trim()
+            .trim_end_matches('#')
+            .trim()
+            .to_string();
+
+        let base = heading_slug(&title);
+        let mut slug = base.clone();
+        let mut n = 1;
+        // an `index` chapter would be written over the book itself
+        while slug == "index" || chapters.iter().any(|c| c.slug == slug) {
+            n += 1;
+            slug = format!("{}-{}", base, n);
+        }
+
+        chapters.push(Chapter { title, slug, body });
+    }
+
+    (preamble, chapters)
+}
+
+/// The heading slugified the same way as the chapter's output path, so that de-duplicating
+/// slugs here also keeps the paths apart. Slashes and dots are dropped first, as they would
+/// otherwise start a directory or an extension.
+fn heading_slug(title: &str) -> String {
+    let slug = slugify(&title.replace(['/', '.'], " "));
+
+    if slug.is_empty() {
+        "chapter".to_string()
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slugs(body: &str) -> Vec<String> {
+        split_chapters(body).1.into_iter().map(|c| c.slug).collect()
+    }
+
+    #[test]
+    fn slugs_are_unique_after_transliteration() {
+        assert_eq!(
+            slugs("# Café\n\n# Cafe\n\n# Index\n"),
+            ["cafe", "cafe-2", "index-2"]
+        );
+    }
+
+    #[test]
+    fn slugs_have_no_directories_or_extensions() {
+        assert_eq!(
+            slugs("# Input/Output\n\n# Version 1.0\n\n# ?!\n"),
+            ["input-output", "version-1-0", "chapter"]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
+
+use crate::{page::Page, Config};
+
+/// Escape the characters that are significant in XML text and attribute values
+pub fn escape_xml(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Parse a page date back into a timestamp. Dates without a time are taken as midnight UTC.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(date.and_time(NaiveTime::MIN).and_utc())
+}
+
+/// RFC 2822 date for a page date, as RSS requires
+fn rfc2822_date(date: &str) -> Option<String> {
+    parse_date(date).map(|date| date.to_rfc2822())
+}
+
+/// RFC 3339 date for a page date, as Atom requires
+fn rfc3339_date(date: &str) -> Option<String> {
+    parse_date(date).map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Builds feed XML one element per line, indented by depth when pretty-printing, or all on
+/// one line otherwise
+struct Lines {
+    output: String,
+    pretty: bool,
+}
+
+impl Lines {
+    fn new(config: &Config) -> Self {
+        Self {
+            output: String::new(),
+            pretty: config.pretty_feeds.unwrap_or(false),
+        }
+    }
+
+    fn push(&mut self, depth: usize, line: &str) {
+        if self.pretty {
+            self.output.push_str(&"  ".repeat(depth));
+            self.output.push_str(line);
+            self.output.push('\n');
+        } else {
+            self.output.push_str(line);
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if !self.pretty {
+            self.output.push('\n');
+        }
+        self.output
+    }
+}
+
+/// The built-in RSS 2.0 feed, used when the site has no `rss.xml` template of its own.
+/// `pages` should be the dated pages, newest first, already capped to the feed length.
+pub fn rss(config: &Config, pages: &[Page]) -> String {
+    let title = escape_xml(&config.title);
+    let link = escape_xml(config.base_url.as_str());
+    let feed_url = escape_xml(config.make_permalink("rss.xml").as_str());
+
+    let mut lines = Lines::new(config);
+    lines.push(0, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    lines.push(
+        0,
+        "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">",
+    );
+    lines.push(1, "<channel>");
+    lines.push(2, &format!("<title>{}</title>", title));
+    lines.push(2, &format!("<link>{}</link>", link));
+    lines.push(2, &format!("<description>{}</description>", title));
+    lines.push(
+        2,
+        &format!(
+            "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+            feed_url
+        ),
+    );
+
+    if let Some(date) = pages.first().and_then(|p| p.date.as_deref()) {
+        if let Some(date) = rfc2822_date(date) {
+            lines.push(2, &format!("<lastBuildDate>{}</lastBuildDate>", date));
+        }
+    }
+
+    for page in pages {
+        let permalink = escape_xml(page.permalink.as_str());
+
+        lines.push(2, "<item>");
+        lines.push(3, &format!("<title>{}</title>", escape_xml(&page.title)));
+        lines.push(3, &format!("<link>{}</link>", permalink));
+        lines.push(
+            3,
+            &format!("<guid isPermaLink=\"true\">{}</guid>", permalink),
+        );
+        // plain RSS has no element for content, so readers take it from the description
+        let description = if page.feed_full_content {
+            &page.content
+        } else {
+            &page.description
+        };
+        lines.push(
+            3,
+            &format!("<description>{}</description>", escape_xml(description)),
+        );
+        if let Some(date) = page.date.as_deref().and_then(rfc2822_date) {
+            lines.push(3, &format!("<pubDate>{}</pubDate>", date));
+        }
+        lines.push(2, "</item>");
+    }
+
+    lines.push(1, "</channel>");
+    lines.push(0, "</rss>");
+
+    lines.finish()
+}
+
+/// The built-in Atom 1.0 feed, used when the site has no `atom.xml` template of its own.
+/// `pages` should be the dated pages, newest first, already capped to the feed length.
+pub fn atom(config: &Config, pages: &[Page]) -> String {
+    let title = escape_xml(&config.title);
+    let link = escape_xml(config.base_url.as_str());
+    let feed_url = escape_xml(config.make_permalink("atom.xml").as_str());
+
+    // a feed without any dated pages has to be updated some time, so use now
+    let updated = pages
+        .first()
+        .and_then(|p| p.date.as_deref())
+        .and_then(rfc3339_date)
+        .unwrap_or_else(|| Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+
+    let mut lines = Lines::new(config);
+    lines.push(0, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    lines.push(0, "<feed xmlns=\"http://www.w3.org/2005/Atom\">");
+    lines.push(1, &format!("<title>{}</title>", title));
+    lines.push(
+        1,
+        &format!(
+            "<link href=\"{}\" rel=\"self\" type=\"application/atom+xml\"/>",
+            feed_url
+        ),
+    );
+    lines.push(1, &format!("<link href=\"{}\"/>", link));
+    lines.push(1, &format!("<id>{}</id>", link));
+    lines.push(1, &format!("<updated>{}</updated>", updated));
+    lines.push(1, &format!("<author><name>{}</name></author>", title));
+
+    for page in pages {
+        let permalink = escape_xml(page.permalink.as_str());
+
+        lines.push(1, "<entry>");
+        lines.push(2, &format!("<title>{}</title>", escape_xml(&page.title)));
+        lines.push(2, &format!("<link href=\"{}\"/>", permalink));
+        lines.push(2, &format!("<id>{}</id>", permalink));
+        if let Some(date) = page.date.as_deref().and_then(rfc3339_date) {
+            lines.push(2, &format!("<updated>{}</updated>", date));
+        }
+        if !page.description.is_empty() {
+            lines.push(
+                2,
+                &format!("<summary>{}</summary>", escape_xml(&page.description)),
+            );
+        }
+        if page.feed_full_content {
+            lines.push(
+                2,
+                &format!(
+                    "<content type=\"html\">{}</content>",
+                    escape_xml(&page.content)
+                ),
+            );
+        }
+        lines.push(1, "</entry>");
+    }
+
+    lines.push(0, "</feed>");
+
+    lines.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+
+    #[test]
+    fn compact_and_pretty_feeds() {
+        let pages = [Page::for_tests(
+            "post/index.html",
+            "Post",
+            Some("2024-01-02"),
+        )];
+
+        let compact = rss(&test_config("pretty_feeds = false"), &pages);
+        assert_eq!(compact.lines().count(), 1);
+        assert!(compact.contains("<item><title>Post</title>"));
+
+        let pretty = rss(&test_config("pretty_feeds = true"), &pages);
+        assert!(pretty.contains("\n    <item>\n      <title>Post</title>\n"));
+
+        let pretty = atom(&test_config("pretty_feeds = true"), &pages);
+        assert!(pretty.contains("\n  <entry>\n    <title>Post</title>\n"));
+    }
+
+    #[test]
+    fn full_content_or_description() {
+        let mut teaser = Page::for_tests("teaser/index.html", "Teaser", Some("2024-01-02"));
+        teaser.description = "Just a teaser".to_string();
+        teaser.content = "<p>Teaser content</p>".to_string();
+        let mut full = Page::for_tests("full/index.html", "Full", Some("2024-01-01"));
+        full.description = "Everything".to_string();
+        full.content = "<p>Full content</p>".to_string();
+        full.feed_full_content = true;
+        let pages = [teaser, full];
+
+        let config = test_config("");
+        let rss = rss(&config, &pages);
+        assert!(rss.contains("<description>Just a teaser</description>"));
+        assert!(rss.contains("<description>&lt;p&gt;Full content&lt;/p&gt;</description>"));
+
+        let atom = atom(&config, &pages);
+        assert!(!atom.contains("Teaser content"));
+        assert!(atom.contains("<summary>Everything</summary>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Full content&lt;/p&gt;</content>"));
+    }
+}
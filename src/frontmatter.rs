@@ -25,3 +25,63 @@ where
 
     Ok((toml::from_str::<D>(frontmatter.trim())?, extra.trim_start()))
 }
+
+/// Convert a TOML value into the equivalent Tera value, preserving arrays and nested
+/// tables. Datetimes become strings, since Tera has no native date type.
+pub fn to_tera_value(value: toml::Value) -> tera::Value {
+    match value {
+        toml::Value::String(s) => tera::Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(a) => tera::Value::Array(a.into_iter().map(to_tera_value).collect()),
+        toml::Value::Table(t) => {
+            tera::Value::Object(t.into_iter().map(|(k, v)| (k, to_tera_value(v))).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values_keep_their_types() {
+        let extra: toml::Value = toml::from_str(
+            r#"
+            count = 3
+            ratio = 0.5
+            featured = true
+            updated = 2024-01-02T03:04:05Z
+            tags = []
+            author = { name = "Amy", social = { mastodon = "@amy" } }
+
+            [[links]]
+            title = "Home"
+            weight = 1
+
+            [[links]]
+            title = "About"
+            weight = 2
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_tera_value(extra),
+            serde_json::json!({
+                "count": 3,
+                "ratio": 0.5,
+                "featured": true,
+                "updated": "2024-01-02T03:04:05Z",
+                "tags": [],
+                "author": { "name": "Amy", "social": { "mastodon": "@amy" } },
+                "links": [
+                    { "title": "Home", "weight": 1 },
+                    { "title": "About", "weight": 2 },
+                ],
+            })
+        );
+    }
+}
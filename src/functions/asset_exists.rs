@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::split_url_suffix;
+
+/// asset_exists(path=) checks whether a site-relative path is a static file, either in one
+/// of the static directories or already in the output, so templates can include optional
+/// assets such as a per-section stylesheet.
+pub struct AssetExists {
+    search_dirs: Vec<PathBuf>,
+}
+
+impl AssetExists {
+    /// `search_dirs` are the same directories `get_url` looks up files in
+    pub fn new(search_dirs: Vec<PathBuf>) -> Self {
+        Self { search_dirs }
+    }
+}
+
+/// The first of `search_dirs` holding a file at a site-relative path. Nothing outside the
+/// site is an asset of it, so paths with `..` (or that only resolve outside a directory
+/// through a symlink) aren't found.
+pub fn find_asset(search_dirs: &[PathBuf], path: &str) -> Option<PathBuf> {
+    let relative = Path::new(split_url_suffix(path.trim()).0.trim_start_matches('/'));
+
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    search_dirs.iter().find_map(|dir| {
+        let file = dir.join(relative).canonicalize().ok()?;
+        let inside = file.starts_with(dir.canonicalize().ok()?);
+        (inside && file.is_file()).then_some(file)
+    })
+}
+
+impl tera::Function for AssetExists {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?
+            .expect("missing path");
+
+        Ok(find_asset(&self.search_dirs, &path).is_some().into())
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::site::Site;
+
+/// get_page(path=) looks up a single page by its name, i.e. its output path such as
+/// `blog/first/index.html`, so a template can show e.g. a featured post.
+pub struct GetPage {
+    site: Arc<RwLock<Site>>,
+}
+
+impl GetPage {
+    pub fn new(site: Arc<RwLock<Site>>) -> Self {
+        Self { site }
+    }
+}
+
+impl tera::Function for GetPage {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?
+            .expect("missing path");
+
+        let site = self.site.try_read().map_err(|e| e.to_string())?;
+
+        let page = site
+            .pages
+            .get(path.trim_start_matches('/'))
+            .ok_or_else(|| format!("get_page: no page named '{}'", path))?;
+
+        Ok(tera::to_value(page)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Page;
+    use tera::Function;
+
+    #[test]
+    fn looks_up_pages_by_name() {
+        let mut site = Site::new();
+        let name = "blog/first/index.html";
+        site.pages
+            .insert(name.to_string(), Page::for_tests(name, "First", None));
+        let get_page = GetPage::new(Arc::new(RwLock::new(site)));
+
+        for path in [name, "/blog/first/index.html"] {
+            let args = [("path".to_string(), path.into())].into();
+            assert_eq!(get_page.call(&args).unwrap()["title"], "First");
+        }
+
+        let args = [("path".to_string(), "blog/second/index.html".into())].into();
+        assert!(get_page.call(&args).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::site::Site;
+
+/// get_pages_by_date(year=, month=) or get_pages_by_date(from=, to=) returns the dated pages
+/// published in that window, newest first. `from` and `to` are inclusive `YYYY-MM-DD` dates,
+/// and either may be omitted for an open-ended range. Undated pages are never included.
+pub struct GetPagesByDate {
+    site: Arc<RwLock<Site>>,
+}
+
+impl GetPagesByDate {
+    pub fn new(site: Arc<RwLock<Site>>) -> Self {
+        Self { site }
+    }
+}
+
+impl tera::Function for GetPagesByDate {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let year = args
+            .get("year")
+            .cloned()
+            .map(tera::from_value::<u32>)
+            .transpose()?;
+        let month = args
+            .get("month")
+            .cloned()
+            .map(tera::from_value::<u32>)
+            .transpose()?;
+        let from = args
+            .get("from")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+        let to = args
+            .get("to")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+
+        // dates are ISO 8601, so a year or month is just a prefix of the date
+        let prefix = match (year, month) {
+            (_, Some(month)) if !(1..=12).contains(&month) => {
+                return Err(format!("month {} is out of range, expected 1-12", month).into())
+            }
+            (Some(year), Some(month)) => Some(format!("{:04}-{:02}-", year, month)),
+            (Some(year), None) => Some(format!("{:04}-", year)),
+            (None, Some(_)) => return Err("month requires a year".into()),
+            (None, None) => None,
+        };
+
+        let site = self.site.try_read().map_err(|e| e.to_string())?;
+
+        let mut pages = site
+            .pages
+            .values()
+            .filter(|p| match &p.date {
+                Some(date) => {
+                    // the bounds are whole days, so a page any time on the `to` day is included
+                    let day = date.get(..10).unwrap_or(date);
+                    prefix
+                        .as_ref()
+                        .is_none_or(|prefix| date.starts_with(prefix))
+                        && from.as_deref().is_none_or(|from| day >= from)
+                        && to.as_deref().is_none_or(|to| day <= to)
+                }
+                None => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        pages.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(tera::to_value(pages)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Page;
+    use tera::Function;
+
+    fn call(args: &[(&str, tera::Value)]) -> tera::Result<Vec<String>> {
+        let mut site = Site::new();
+        for (name, date) in [
+            ("jan/index.html", Some("2024-01-31")),
+            ("feb/index.html", Some("2024-02-01T09:30:00Z")),
+            ("march/index.html", Some("2024-03-15")),
+            ("old/index.html", Some("2023-02-10")),
+            ("undated/index.html", None),
+        ] {
+            site.pages
+                .insert(name.to_string(), Page::for_tests(name, name, date));
+        }
+
+        let args = args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        let pages = GetPagesByDate::new(Arc::new(RwLock::new(site))).call(&args)?;
+
+        Ok(pages
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["date"].as_str().unwrap().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn year_and_month() {
+        assert_eq!(
+            call(&[("year", 2024.into())]).unwrap(),
+            ["2024-03-15", "2024-02-01T09:30:00Z", "2024-01-31"]
+        );
+        assert_eq!(
+            call(&[("year", 2024.into()), ("month", 2.into())]).unwrap(),
+            ["2024-02-01T09:30:00Z"]
+        );
+    }
+
+    #[test]
+    fn months_out_of_range() {
+        assert!(call(&[("year", 2024.into()), ("month", 13.into())]).is_err());
+        assert!(call(&[("year", 2024.into()), ("month", 0.into())]).is_err());
+        assert!(call(&[("month", 2.into())]).is_err());
+    }
+
+    #[test]
+    fn inclusive_from_and_to() {
+        assert_eq!(
+            call(&[("from", "2024-01-31".into()), ("to", "2024-02-01".into())]).unwrap(),
+            ["2024-02-01T09:30:00Z", "2024-01-31"]
+        );
+        assert_eq!(
+            call(&[("to", "2024-01-31".into())]).unwrap(),
+            ["2024-01-31", "2023-02-10"]
+        );
+        assert_eq!(
+            call(&[("from", "2024-03-01".into())]).unwrap(),
+            ["2024-03-15"]
+        );
+    }
+}
@@ -1,20 +1,51 @@
 use std::{
     collections::HashMap,
-    path::PathBuf,
+    path::{Component, Path, PathBuf},
     sync::{Arc, RwLock},
 };
 
-use crate::{section::Section, site::Site};
+use crate::{
+    page::{self, PageSummary},
+    section::{pager_path, Section},
+    site::Site,
+    slugify, Config,
+};
 
 /// Unlike Zola, you don't have to declare sections. get_section() just recursively
-/// grabs all pages that are children of the requested section.
+/// grabs all pages that are children of the requested section. Immediate child
+/// directories that do contain an `_index.md` are listed as `subsections`.
+/// With `fields="summary"`, pages are listed without their `content` and `content_text`.
+/// Pages are newest first, or sorted with `sort_by=` any key a page's `sort_by` accepts, and
+/// `reverse=true` flips either order. Pages without a date always come last by date.
+/// With `paginate_by=`, only that many pages are listed, from the given `page=` (counting
+/// from 1), along with links to the previous and next pages of the section.
 pub struct GetSection {
     site: Arc<RwLock<Site>>,
+    config: Config,
 }
 
 impl GetSection {
-    pub fn new(site: Arc<RwLock<Site>>) -> Self {
-        Self { site }
+    pub fn new(site: Arc<RwLock<Site>>, config: Config) -> Self {
+        Self { site, config }
+    }
+
+    /// Page names are slugified paths relative to the content directory, so accept
+    /// `content/blog/_index.md`, `blog/_index.md` or plain `blog` for the same section.
+    fn section_prefix(path: &str) -> String {
+        let mut prefix = Path::new(path.trim())
+            .components()
+            .filter(|c| c != &Component::CurDir)
+            .collect::<PathBuf>();
+
+        if let Ok(stripped) = prefix.strip_prefix("content") {
+            prefix = stripped.to_path_buf();
+        }
+
+        if prefix.extension().is_some() {
+            prefix.pop();
+        }
+
+        slugify(&prefix.to_string_lossy())
     }
 }
 
@@ -26,24 +57,104 @@ impl tera::Function for GetSection {
             .map(tera::from_value::<String>)
             .transpose()?
             .expect("missing path");
+        let fields = args
+            .get("fields")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+        let sort_by = args
+            .get("sort_by")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+        let reverse = args
+            .get("reverse")
+            .cloned()
+            .map(tera::from_value::<bool>)
+            .transpose()?
+            .unwrap_or(false);
+        let paginate_by = args
+            .get("paginate_by")
+            .cloned()
+            .map(tera::from_value::<usize>)
+            .transpose()?;
+        let current_page = args
+            .get("page")
+            .cloned()
+            .map(tera::from_value::<usize>)
+            .transpose()?
+            .unwrap_or(1)
+            .max(1);
 
-        let mut prefix = PathBuf::from(path);
-        prefix.pop();
-        let prefix = prefix.to_string_lossy().to_string();
+        let summary_only = match fields.as_deref() {
+            None | Some("all") => false,
+            Some("summary") => true,
+            Some(other) => {
+                return Err(format!(
+                    "get_section: unknown fields '{}', expected \"all\" or \"summary\"",
+                    other
+                )
+                .into())
+            }
+        };
 
-        let mut section = Section { pages: vec![] };
+        let prefix = Self::section_prefix(&path);
 
         let site = self.site.try_read().map_err(|e| e.to_string())?;
 
-        for page in site.pages.values() {
-            if page.name.starts_with(&prefix) {
-                section.pages.push(page.clone())
-            }
+        let mut section = site.section(&prefix);
+
+        // the section is already newest first, which is the default
+        let (key, reverse) = match sort_by.as_deref() {
+            Some(key) => (key, reverse),
+            None => ("date", !reverse),
+        };
+        page::sort_pages(&mut section.pages, key, reverse)
+            .map_err(|e| format!("get_section: {}", e))?;
+
+        if let Some(paginate_by) = paginate_by.filter(|n| *n > 0) {
+            let pager_url = |number| self.config.make_url(&pager_path(&prefix, number));
+
+            section.total_pages = section.total.div_ceil(paginate_by).max(1);
+            section.current_page = current_page.min(section.total_pages);
+            section.previous =
+                (section.current_page > 1).then(|| pager_url(section.current_page - 1));
+            section.next = (section.current_page < section.total_pages)
+                .then(|| pager_url(section.current_page + 1));
+
+            section.pages = section
+                .pages
+                .into_iter()
+                .skip((section.current_page - 1) * paginate_by)
+                .take(paginate_by)
+                .collect();
         }
 
-        section.pages.sort_by_key(|p| p.date.clone());
-        section.pages.reverse();
+        if summary_only {
+            return Ok(tera::to_value(Section {
+                pages: section.pages.iter().map(PageSummary::from).collect(),
+                total: section.total,
+                subsections: section.subsections,
+                current_page: section.current_page,
+                total_pages: section.total_pages,
+                previous: section.previous,
+                next: section.next,
+            })?);
+        }
 
         Ok(tera::to_value(section)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section_prefix_accepts_index_paths() {
+        assert_eq!(GetSection::section_prefix("blog/_index.md"), "blog");
+        assert_eq!(GetSection::section_prefix("content/blog/_index.md"), "blog");
+        assert_eq!(GetSection::section_prefix("./blog"), "blog");
+        assert_eq!(GetSection::section_prefix("blog"), "blog");
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use serde::Serialize;
+
+use crate::{page::Page, site::Site};
+
+/// The chronologically adjacent pages that share a taxonomy term with the current page.
+/// `higher` is the next newer page, `lower` the next older one.
+#[derive(Serialize)]
+struct Siblings {
+    lower: Option<Page>,
+    higher: Option<Page>,
+}
+
+/// get_taxonomy_siblings(kind=, term=, path=) finds the neighbours of a page within
+/// a single taxonomy term. `path` may be either the page's name or its permalink,
+/// so templates can pass `current_url` directly.
+pub struct GetTaxonomySiblings {
+    site: Arc<RwLock<Site>>,
+}
+
+impl GetTaxonomySiblings {
+    pub fn new(site: Arc<RwLock<Site>>) -> Self {
+        Self { site }
+    }
+}
+
+impl tera::Function for GetTaxonomySiblings {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let kind = args
+            .get("kind")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?
+            .expect("missing kind");
+        let term = args
+            .get("term")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?
+            .expect("missing term");
+        let path = args
+            .get("path")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?
+            .expect("missing path");
+
+        let site = self.site.try_read().map_err(|e| e.to_string())?;
+
+        let mut pages = site
+            .pages
+            .values()
+            .filter(|p| p.date.is_some())
+            .filter(|p| {
+                p.taxonomies
+                    .get(&kind)
+                    .map(|terms| terms.contains(&term))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        pages.sort_by(|a, b| (&a.date, &a.name).cmp(&(&b.date, &b.name)));
+
+        let index = pages
+            .iter()
+            .position(|p| p.name == path || p.permalink.as_str() == path)
+            .ok_or_else(|| format!("no dated page {} with {} = {}", path, kind, term))?;
+
+        let siblings = Siblings {
+            lower: index.checked_sub(1).map(|i| pages[i].clone()),
+            higher: pages.get(index + 1).cloned().cloned(),
+        };
+
+        Ok(tera::to_value(siblings)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Function;
+
+    #[test]
+    fn neighbours_within_a_term() {
+        let mut site = Site::new();
+        for (name, date, tags) in [
+            ("one/index.html", Some("2024-01-01"), vec!["rust"]),
+            ("two/index.html", Some("2024-02-01"), vec!["web"]),
+            ("three/index.html", Some("2024-03-01"), vec!["rust", "web"]),
+            ("four/index.html", Some("2024-04-01"), vec!["rust"]),
+            ("undated/index.html", None, vec!["rust"]),
+        ] {
+            let mut page = Page::for_tests(name, name, date);
+            page.taxonomies.insert(
+                "tags".to_string(),
+                tags.into_iter().map(str::to_string).collect(),
+            );
+            site.pages.insert(name.to_string(), page);
+        }
+        let siblings = GetTaxonomySiblings::new(Arc::new(RwLock::new(site)));
+
+        let call = |path: &str| {
+            let args = [
+                ("kind".to_string(), "tags".into()),
+                ("term".to_string(), "rust".into()),
+                ("path".to_string(), path.into()),
+            ]
+            .into();
+            siblings.call(&args)
+        };
+
+        let result = call("three/index.html").unwrap();
+        assert_eq!(result["lower"]["title"], "one/index.html");
+        assert_eq!(result["higher"]["title"], "four/index.html");
+
+        let result = call("https://example.com/one/index.html").unwrap();
+        assert!(result["lower"].is_null());
+        assert_eq!(result["higher"]["title"], "three/index.html");
+
+        assert!(call("two/index.html").is_err());
+        assert!(call("undated/index.html").is_err());
+    }
+}
@@ -1,23 +1,19 @@
-use std::collections::HashMap;
-use url::Url;
+use std::collections::{HashMap, HashSet};
 
-use crate::{slugify, Taxonomy};
+use crate::Config;
 
+/// get_taxonomy_url(kind=, name=) returns the URL of a term's page, and
+/// get_taxonomy_url(kind=, index=true) the URL of the taxonomy's own list page, which
+/// only exists for taxonomies with a `{taxonomy}/list.html` template.
 pub struct GetTaxonomyURL {
-    base_url: Url,
-    taxonomies: HashMap<String, Taxonomy>,
+    config: Config,
+    listed: HashSet<String>,
 }
 
 impl GetTaxonomyURL {
-    pub fn new(base_url: Url, taxonomies: &[Taxonomy]) -> Self {
-        let taxonomies = taxonomies
-            .iter()
-            .map(|t| (t.name.to_string(), t.clone()))
-            .collect::<HashMap<String, Taxonomy>>();
-        GetTaxonomyURL {
-            base_url,
-            taxonomies,
-        }
+    /// `listed` are the names of the taxonomies that have a list page
+    pub fn new(config: Config, listed: HashSet<String>) -> Self {
+        GetTaxonomyURL { config, listed }
     }
 }
 
@@ -33,12 +29,31 @@ impl tera::Function for GetTaxonomyURL {
             .get("name")
             .cloned()
             .map(tera::from_value::<String>)
+            .transpose()?;
+        let index = args
+            .get("index")
+            .cloned()
+            .map(tera::from_value::<bool>)
             .transpose()?
-            .expect("missing name");
+            .unwrap_or(false);
 
-        if let Some(taxonomy) = self.taxonomies.get(&kind) {
-            let path = slugify(&(taxonomy.name.clone() + "/" + &name));
-            let result = self.base_url.join(path.trim()).unwrap();
+        if let Some(taxonomy) = self.config.taxonomies.iter().find(|t| t.name == kind) {
+            let result = match (index, name) {
+                (true, None) if !self.listed.contains(&kind) => {
+                    return Err(format!(
+                        "taxonomy {} has no list page, add a {} template for one",
+                        kind,
+                        taxonomy.list_template_name()
+                    )
+                    .into())
+                }
+                (true, None) => self.config.make_url(&format!("{}/", taxonomy.slug())),
+                (false, Some(name)) => self
+                    .config
+                    .make_permalink(&taxonomy.term_output_path(name.trim())),
+                (true, Some(_)) => return Err("index=true can't be combined with a name".into()),
+                (false, None) => return Err("missing name".into()),
+            };
 
             Ok(tera::to_value::<String>(result.into())?)
         } else {
@@ -50,3 +65,39 @@ impl tera::Function for GetTaxonomyURL {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+    use tera::Function;
+
+    fn call(listed: &[&str], args: &[(&str, tera::Value)]) -> tera::Result<tera::Value> {
+        let mut config = test_config("");
+        config.taxonomies = vec![toml::from_str("name = \"tags\"\nslug = \"topics\"").unwrap()];
+        let listed = listed.iter().map(|s| s.to_string()).collect();
+        let args = args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        GetTaxonomyURL::new(config, listed).call(&args)
+    }
+
+    #[test]
+    fn term_url() {
+        assert_eq!(
+            call(&[], &[("kind", "tags".into()), ("name", "Web Dev".into())]).unwrap(),
+            "https://example.com/topics/web-dev/"
+        );
+    }
+
+    #[test]
+    fn index_url_needs_a_list_page() {
+        let args = [("kind", "tags".into()), ("index", true.into())];
+        assert_eq!(
+            call(&["tags"], &args).unwrap(),
+            "https://example.com/topics/"
+        );
+        assert!(call(&[], &args).is_err());
+    }
+}
@@ -1,13 +1,36 @@
-use std::collections::HashMap;
-use url::Url;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs, path::PathBuf};
 
+use crate::{functions::asset_exists::find_asset, Config};
+
+/// get_url(path=, query=, cachebust=, anchor=) builds an absolute URL from a site-relative path.
+/// The result is always assembled as path, then query (with the cachebust hash appended
+/// last), then fragment.
 pub struct GetURL {
-    base_url: Url,
+    config: Config,
+    search_dirs: Vec<PathBuf>,
 }
 
 impl GetURL {
-    pub fn new(base_url: Url) -> Self {
-        GetURL { base_url }
+    /// `search_dirs` are checked in order when looking up a file to cachebust
+    pub fn new(config: Config, search_dirs: Vec<PathBuf>) -> Self {
+        GetURL {
+            config,
+            search_dirs,
+        }
+    }
+
+    fn find_file(&self, path: &str) -> Option<PathBuf> {
+        find_asset(&self.search_dirs, path)
+    }
+
+    fn file_hash(&self, path: &str) -> tera::Result<String> {
+        let file = self
+            .find_file(path)
+            .ok_or_else(|| format!("can't cachebust {}, file not found", path))?;
+        let contents = fs::read(&file).map_err(|e| e.to_string())?;
+
+        Ok(format!("{:x}", Sha256::digest(contents)))
     }
 }
 
@@ -19,8 +42,54 @@ impl tera::Function for GetURL {
             .map(tera::from_value::<String>)
             .transpose()?
             .expect("missing path");
+        let query = args
+            .get("query")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+        let cachebust = args
+            .get("cachebust")
+            .cloned()
+            .map(tera::from_value::<bool>)
+            .transpose()?
+            .unwrap_or(false);
+        let anchor = args
+            .get("anchor")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+
+        let path = path.trim();
+        // static files are copied as they are, so only paths to pages are slugified
+        let mut result = if self.find_file(path).is_some() {
+            self.config.make_static_url(path)
+        } else {
+            self.config.make_url(path)
+        };
+
+        let hash = if cachebust {
+            Some(format!("h={}", self.file_hash(path)?))
+        } else {
+            None
+        };
+
+        let query = result
+            .query()
+            .map(str::to_string)
+            .into_iter()
+            .chain(query.map(|q| q.trim_start_matches('?').to_string()))
+            .chain(hash)
+            .filter(|q| !q.is_empty())
+            .collect::<Vec<_>>()
+            .join("&");
 
-        let result = self.base_url.join(path.trim()).unwrap();
+        if !query.is_empty() {
+            result.set_query(Some(&query));
+        }
+
+        if let Some(anchor) = anchor {
+            result.set_fragment(Some(anchor.trim_start_matches('#')));
+        }
 
         Ok(tera::to_value::<String>(result.into())?)
     }
@@ -29,3 +98,125 @@ impl tera::Function for GetURL {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+    use tera::Function;
+
+    /// A `GetURL` that finds `style.css` (containing `body {}`) as a static file
+    fn get_url() -> GetURL {
+        let dir = std::env::temp_dir().join(format!("mini-ssg-get-url-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("style.css"), "body {}").unwrap();
+
+        GetURL::new(test_config(""), vec![dir])
+    }
+
+    fn call(args: &[(&str, tera::Value)]) -> String {
+        let args = args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        get_url().call(&args).unwrap().as_str().unwrap().to_string()
+    }
+
+    fn hash() -> String {
+        format!("{:x}", Sha256::digest("body {}"))
+    }
+
+    #[test]
+    fn path_only() {
+        assert_eq!(
+            call(&[("path", "Blog Posts/".into())]),
+            "https://example.com/blog-posts/"
+        );
+        assert_eq!(
+            call(&[("path", "/style.css".into())]),
+            "https://example.com/style.css"
+        );
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            call(&[("path", "search".into()), ("query", "?q=rust".into())]),
+            "https://example.com/search?q=rust"
+        );
+        assert_eq!(
+            call(&[("path", "search?page=2".into()), ("query", "q=rust".into())]),
+            "https://example.com/search?page=2&q=rust"
+        );
+    }
+
+    #[test]
+    fn cachebust() {
+        assert_eq!(
+            call(&[("path", "style.css".into()), ("cachebust", true.into())]),
+            format!("https://example.com/style.css?h={}", hash())
+        );
+        assert!(get_url()
+            .call(
+                &[
+                    ("path".to_string(), "missing.css".into()),
+                    ("cachebust".to_string(), true.into())
+                ]
+                .into()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn cachebust_outside_the_site() {
+        let name = format!("mini-ssg-get-url-{}-outside.css", std::process::id());
+        fs::write(std::env::temp_dir().join(&name), "body {}").unwrap();
+
+        for path in [format!("../{}", name), format!("/../{}", name)] {
+            let args = [
+                ("path".to_string(), path.into()),
+                ("cachebust".to_string(), true.into()),
+            ];
+            assert!(get_url().call(&args.into()).is_err());
+        }
+
+        fs::remove_file(std::env::temp_dir().join(&name)).unwrap();
+    }
+
+    #[test]
+    fn anchor() {
+        assert_eq!(
+            call(&[("path", "about".into()), ("anchor", "#team".into())]),
+            "https://example.com/about#team"
+        );
+    }
+
+    #[test]
+    fn query_cachebust_and_anchor() {
+        assert_eq!(
+            call(&[
+                ("path", "style.css".into()),
+                ("query", "v=1".into()),
+                ("cachebust", true.into()),
+                ("anchor", "top".into()),
+            ]),
+            format!("https://example.com/style.css?v=1&h={}#top", hash())
+        );
+        assert_eq!(
+            call(&[
+                ("path", "about".into()),
+                ("query", "v=1".into()),
+                ("anchor", "top".into()),
+            ]),
+            "https://example.com/about?v=1#top"
+        );
+        assert_eq!(
+            call(&[
+                ("path", "style.css".into()),
+                ("cachebust", true.into()),
+                ("anchor", "top".into()),
+            ]),
+            format!("https://example.com/style.css?h={}#top", hash())
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::frontmatter::to_tera_value;
+
+/// load_data(path=) reads and parses a single data file, and load_data(glob=) reads every
+/// matching file into an array, sorted by filename. Paths are relative to the site root
+/// and may not escape it. The format is picked by file extension (`toml` or `json`).
+/// Each file is only parsed once per build, however many pages load it.
+pub struct LoadData {
+    home: PathBuf,
+    /// Parsed files, by path and modification time, so a file edited mid-build is reloaded
+    cache: Mutex<HashMap<(PathBuf, SystemTime), tera::Value>>,
+    files: DataFiles,
+}
+
+/// The data files loaded by templates, so that watch mode can rebuild when they change
+pub type DataFiles = Arc<Mutex<BTreeSet<PathBuf>>>;
+
+impl LoadData {
+    /// Every file loaded is added to `files`
+    pub fn new(home: PathBuf, files: DataFiles) -> Self {
+        Self {
+            home,
+            cache: Mutex::new(HashMap::new()),
+            files,
+        }
+    }
+
+    /// Resolve a site-relative path, refusing anything outside the site root
+    fn resolve(&self, path: &Path) -> tera::Result<PathBuf> {
+        let home = self.home.canonicalize().map_err(|e| e.to_string())?;
+        let file = home
+            .join(path)
+            .canonicalize()
+            .map_err(|e| format!("can't load {}: {}", path.display(), e))?;
+
+        if !file.starts_with(&home) {
+            return Err(format!("can't load {}, it is outside the site", path.display()).into());
+        }
+
+        Ok(file)
+    }
+
+    fn load(&self, file: &Path) -> tera::Result<tera::Value> {
+        let modified = fs::metadata(file)
+            .and_then(|m| m.modified())
+            .map_err(|e| e.to_string())?;
+        let key = (file.to_path_buf(), modified);
+        self.files.lock().unwrap().insert(file.to_path_buf());
+
+        if let Some(value) = self.cache.lock().unwrap().get(&key) {
+            return Ok(value.clone());
+        }
+
+        let value = self.parse(file)?;
+        self.cache.lock().unwrap().insert(key, value.clone());
+
+        Ok(value)
+    }
+
+    fn parse(&self, file: &Path) -> tera::Result<tera::Value> {
+        let contents = fs::read_to_string(file).map_err(|e| e.to_string())?;
+
+        match file.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(to_tera_value(
+                toml::from_str(&contents).map_err(|e| e.to_string())?,
+            )),
+            Some("json") => Ok(serde_json::from_str(&contents).map_err(|e| e.to_string())?),
+            _ => Err(format!("unsupported data file format {}", file.display()).into()),
+        }
+    }
+}
+
+impl tera::Function for LoadData {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+        let pattern = args
+            .get("glob")
+            .cloned()
+            .map(tera::from_value::<String>)
+            .transpose()?;
+
+        match (path, pattern) {
+            (Some(path), None) => self.load(&self.resolve(Path::new(&path))?),
+            (None, Some(pattern)) => {
+                let pattern = self.home.join(pattern);
+                let mut files = glob::glob(&pattern.to_string_lossy())
+                    .map_err(|e| e.to_string())?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.to_string())?;
+                files.sort_by(|a, b| (a.file_name(), a).cmp(&(b.file_name(), b)));
+
+                let values = files
+                    .iter()
+                    .filter(|f| f.is_file())
+                    .map(|f| self.resolve(f).and_then(|f| self.load(&f)))
+                    .collect::<tera::Result<Vec<_>>>()?;
+
+                Ok(tera::Value::Array(values))
+            }
+            _ => Err("load_data requires exactly one of path or glob".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Function;
+
+    #[test]
+    fn globs_and_the_site_boundary() {
+        let root = std::env::temp_dir().join(format!("mini-ssg-load-data-{}", std::process::id()));
+        let home = root.join("site");
+        fs::create_dir_all(home.join("data")).unwrap();
+        fs::write(home.join("data/b.json"), r#"{"name": "b"}"#).unwrap();
+        fs::write(home.join("data/a.toml"), "name = \"a\"").unwrap();
+        fs::write(root.join("outside.toml"), "name = \"outside\"").unwrap();
+
+        let files = DataFiles::default();
+        let load_data = LoadData::new(home.clone(), files.clone());
+        let call = |name: &str, value: &str| {
+            let args = [(name.to_string(), value.into())].into();
+            load_data.call(&args)
+        };
+
+        let names: Vec<_> = call("glob", "data/*")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["name"].clone())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(call("path", "data/b.json").unwrap()["name"], "b");
+        assert_eq!(files.lock().unwrap().len(), 2);
+
+        assert!(call("path", "../outside.toml").is_err());
+        assert!(call("glob", "../*.toml").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,6 +1,16 @@
 use pulldown_cmark::html;
 
-pub struct Markdown {}
+use crate::MarkdownConfig;
+
+pub struct Markdown {
+    config: MarkdownConfig,
+}
+
+impl Markdown {
+    pub fn new(config: MarkdownConfig) -> Self {
+        Self { config }
+    }
+}
 
 impl tera::Filter for Markdown {
     fn filter(
@@ -10,11 +20,16 @@ impl tera::Filter for Markdown {
     ) -> tera::Result<tera::Value> {
         let input = tera::from_value::<String>(value.clone())?;
 
-        let parser = pulldown_cmark::Parser::new(&input);
+        Ok(tera::to_value(render(&input, &self.config))?)
+    }
+}
 
-        let mut contents = String::new();
-        html::push_html(&mut contents, parser);
+/// Render a snippet of markdown exactly as the `markdown` filter would
+pub fn render(input: &str, config: &MarkdownConfig) -> String {
+    let parser = crate::markdown::parser(input, config);
 
-        Ok(tera::to_value(contents)?)
-    }
+    let mut contents = String::new();
+    html::push_html(&mut contents, parser);
+
+    contents
 }
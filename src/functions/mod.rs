@@ -1,4 +1,9 @@
+pub mod asset_exists;
+pub mod get_page;
+pub mod get_pages_by_date;
 pub mod get_section;
+pub mod get_taxonomy_siblings;
 pub mod get_taxonomy_url;
 pub mod get_url;
+pub mod load_data;
 pub mod markdown;
@@ -1,40 +1,507 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
-use syntect::{highlighting::ThemeSet, html::highlighted_html_for_string, parsing::SyntaxSet};
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    html::{
+        append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+        line_tokens_to_classed_spans, start_highlighted_html_snippet, ClassStyle,
+        IncludeBackground,
+    },
+    parsing::{ParseState, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
 
-use crate::Context;
+use crate::{warnings::Warnings, Context};
 
-pub struct Highlighter {
+/// Options parsed from the info string of a fenced code block, e.g. `rust,linenos,linenostart=42`
+pub struct CodeBlockOptions {
+    pub lang: String,
+    pub linenos: bool,
+    pub linenostart: usize,
+    /// Filename shown as a caption above the block
+    pub name: Option<String>,
+    /// Ranges of lines to emphasise, counted from the first line of the block (regardless of
+    /// `linenostart`), from `hl_lines=1-3,5` or `hl_lines=1-3 5`
+    pub hl_lines: Vec<(usize, usize)>,
+}
+
+impl Default for CodeBlockOptions {
+    fn default() -> Self {
+        Self {
+            lang: String::new(),
+            linenos: false,
+            linenostart: 1,
+            name: None,
+            hl_lines: vec![],
+        }
+    }
+}
+
+impl CodeBlockOptions {
+    pub fn parse(info: &str) -> anyhow::Result<Self> {
+        let mut options = Self::default();
+        // the commas between `hl_lines` ranges are also the separator between options, so
+        // any bare line numbers that follow it are taken to be more of its ranges
+        let mut in_hl_lines = false;
+
+        for part in info.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if in_hl_lines {
+                if let Some(range) = line_range(part) {
+                    options.hl_lines.push(range);
+                    continue;
+                }
+                in_hl_lines = false;
+            }
+
+            match part.split_once('=') {
+                Some(("hl_lines", value)) => {
+                    for range in value.split_whitespace() {
+                        options.hl_lines.push(
+                            line_range(range)
+                                .ok_or_else(|| anyhow!("invalid hl_lines '{}'", value))?,
+                        );
+                    }
+                    in_hl_lines = true;
+                }
+                Some(("linenostart", value)) => {
+                    options.linenostart = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("invalid linenostart '{}'", value))?;
+                }
+                Some(("name", value)) => options.name = Some(value.trim().to_string()),
+                None if part == "linenos" => options.linenos = true,
+                None if options.lang.is_empty() => options.lang = part.to_string(),
+                // unrecognised options (e.g. rustdoc's `ignore`) are allowed and skipped
+                _ => {}
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Whether the line at this 1-based position in the block is in `hl_lines`
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.hl_lines
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&line))
+    }
+}
+
+/// A line number or an inclusive range of them, like `5` or `1-3`
+fn line_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once('-') {
+        Some((start, end)) => Some((start.trim().parse().ok()?, end.trim().parse().ok()?)),
+        None => {
+            let line = range.trim().parse().ok()?;
+            Some((line, line))
+        }
+    }
+}
+
+/// Language tokens that explicitly request unhighlighted output
+const PLAIN_TEXT_TOKENS: [&str; 4] = ["text", "txt", "plain", "plaintext"];
+
+/// Theme used unless the site picks one with `highlight_theme`
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Classes used with `highlight_css`, prefixed so they can't clash with the site's own
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Highlighted HTML from previous builds, keyed by a hash of everything that affects it
+struct HighlightCache {
+    path: PathBuf,
+    entries: HashMap<String, String>,
+    /// Entries used by this build, which are the only ones saved
+    used: HashMap<String, String>,
+}
+
+struct Syntect {
     syntax_set: SyntaxSet,
     theme_set: ThemeSet,
+    theme: String,
+    /// Hash of the theme's settings, so that cached output is discarded when a theme file
+    /// is edited
+    theme_hash: String,
+    /// Emit classes for the `syntax.css` stylesheet, rather than inline styles
+    classes: bool,
+}
+
+pub struct Highlighter {
+    /// Not loaded at all when highlighting is disabled with `--no-highlight`
+    syntect: Option<Syntect>,
+    default_language: Option<String>,
+    warnings: Warnings,
+    cache: Option<Mutex<HighlightCache>>,
 }
 
 impl Highlighter {
-    pub fn new(context: &Context) -> anyhow::Result<Self> {
-        let mut syntax_set_builder = SyntaxSet::load_defaults_newlines().into_builder();
-        syntax_set_builder.add_from_folder(context.absolute(Path::new("syntaxes")), true)?;
-        let syntax_set = syntax_set_builder.build();
+    pub fn new(context: &Context, cache_dir: Option<&Path>) -> anyhow::Result<Self> {
+        let syntect = if context.no_highlight {
+            None
+        } else {
+            let mut syntax_set_builder = SyntaxSet::load_defaults_newlines().into_builder();
+            syntax_set_builder.add_from_folder(context.absolute(Path::new("syntaxes")), true)?;
+
+            let mut theme_set = ThemeSet::load_defaults();
+            // not `themes/`, which holds whole site themes
+            let themes_dir = context.absolute("highlight_themes");
+            if themes_dir.is_dir() {
+                theme_set.add_from_folder(themes_dir)?;
+            }
+
+            let theme = context
+                .config
+                .highlight_theme
+                .clone()
+                .unwrap_or_else(|| DEFAULT_THEME.to_string());
+            if !theme_set.themes.contains_key(&theme) {
+                return Err(anyhow!(
+                    "highlight_theme '{}' not found, available themes are: {}",
+                    theme,
+                    theme_set
+                        .themes
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            let theme_hash = format!(
+                "{:x}",
+                Sha256::digest(serde_json::to_vec(&theme_set.themes[&theme])?)
+            );
 
-        let theme_set = ThemeSet::load_defaults();
+            Some(Syntect {
+                syntax_set: syntax_set_builder.build(),
+                theme_set,
+                theme,
+                theme_hash,
+                classes: context.config.highlight_css,
+            })
+        };
+
+        // a missing or unreadable cache just means starting from scratch
+        let cache = cache_dir.map(|dir| {
+            let path = dir.join("highlight.json");
+            let entries = fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok())
+                .unwrap_or_default();
+
+            Mutex::new(HighlightCache {
+                path,
+                entries,
+                used: HashMap::new(),
+            })
+        });
 
         Ok(Self {
-            syntax_set,
-            theme_set,
+            syntect,
+            default_language: context.config.default_code_language.clone(),
+            warnings: context.warnings.clone(),
+            cache,
         })
     }
 
-    pub fn highlight(&self, lang: &str, input: &str) -> anyhow::Result<String> {
-        let syntax = self
-            .syntax_set
-            .find_syntax_by_token(lang)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
+    /// Write out the entries used by this build, dropping any that have gone stale
+    pub fn save_cache(&self) -> anyhow::Result<()> {
+        if let Some(cache) = &self.cache {
+            let cache = cache.lock().map_err(|e| anyhow!(e.to_string()))?;
+
+            if let Some(dir) = cache.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&cache.path, serde_json::to_string(&cache.used)?)?;
+        }
 
-        Ok(highlighted_html_for_string(
-            input,
-            &self.syntax_set,
-            syntax,
+        Ok(())
+    }
+
+    /// The stylesheet for highlighted code, if it's highlighted with classes (`highlight_css`)
+    pub fn stylesheet(&self) -> anyhow::Result<Option<String>> {
+        match &self.syntect {
+            Some(syntect) if syntect.classes => Ok(Some(css_for_theme_with_class_style(
+                &syntect.theme_set.themes[&syntect.theme],
+                CLASS_STYLE,
+            )?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn highlight(&self, options: &CodeBlockOptions, input: &str) -> anyhow::Result<String> {
+        let lang = match (options.lang.as_str(), &self.default_language) {
+            ("", Some(default)) => default.as_str(),
+            (lang, _) => lang,
+        };
+
+        let Some(Syntect {
+            syntax_set,
+            theme_set,
             theme,
-        )?)
+            theme_hash,
+            classes,
+        }) = &self.syntect
+        else {
+            let class = if lang.is_empty() {
+                String::new()
+            } else {
+                format!(" class=\"language-{}\"", tera::escape_html(lang))
+            };
+            return Ok(format!(
+                "<pre><code{}>{}</code></pre>\n",
+                class,
+                tera::escape_html(input)
+            ));
+        };
+
+        let syntax = syntax_set.find_syntax_by_token(lang).unwrap_or_else(|| {
+            if !lang.is_empty() && !PLAIN_TEXT_TOKENS.contains(&lang) {
+                self.warnings.warn(
+                    "missing syntaxes",
+                    format!("no syntax found for '{}', highlighting as plain text", lang),
+                );
+            }
+            syntax_set.find_syntax_plain_text()
+        });
+
+        let key = self.cache.as_ref().map(|_| {
+            let mut hasher = Sha256::new();
+            for part in [
+                env!("CARGO_PKG_VERSION"),
+                &syntax.name,
+                theme_hash,
+                &classes.to_string(),
+                &options.linenos.to_string(),
+                &options.linenostart.to_string(),
+                &format!("{:?}", options.hl_lines),
+                input,
+            ] {
+                hasher.update(part.len().to_le_bytes());
+                hasher.update(part);
+            }
+            format!("{:x}", hasher.finalize())
+        });
+
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            let mut cache = cache.lock().map_err(|e| anyhow!(e.to_string()))?;
+            if let Some(output) = cache.entries.get(key).cloned() {
+                cache.used.insert(key.clone(), output.clone());
+                return Ok(output);
+            }
+        }
+
+        let theme = &theme_set.themes[theme];
+        let is_diff = syntax.name == "Diff";
+
+        let mut lines = if *classes {
+            Lines::Classed(ClassedLines::new(syntax))
+        } else {
+            let (output, background) = start_highlighted_html_snippet(theme);
+            Lines::Styled(HighlightLines::new(syntax, theme), output, background)
+        };
+        let mut output = match &mut lines {
+            Lines::Classed(_) => "<pre class=\"hl-code\">".to_string(),
+            Lines::Styled(_, output, _) => std::mem::take(output),
+        };
+
+        for (i, line) in LinesWithEndings::from(input).enumerate() {
+            let mut line_classes = vec![];
+            if is_diff {
+                line_classes.extend(diff_line_class(line));
+            }
+            if options.is_highlighted(i + 1) {
+                line_classes.push("highlight-line");
+            }
+            if !line_classes.is_empty() {
+                output.push_str(&format!("<span class=\"{}\">", line_classes.join(" ")));
+            }
+            if options.linenos {
+                output.push_str(&format!(
+                    "<span class=\"lineno\">{}</span>",
+                    options.linenostart + i
+                ));
+            }
+            match &mut lines {
+                Lines::Classed(classed) => {
+                    output.push_str(&classed.highlight_line(line, syntax_set)?);
+                }
+                Lines::Styled(highlighter, _, background) => {
+                    let regions = highlighter.highlight_line(line, syntax_set)?;
+                    append_highlighted_html_for_styled_line(
+                        &regions,
+                        IncludeBackground::IfDifferent(*background),
+                        &mut output,
+                    )?;
+                }
+            }
+            if !line_classes.is_empty() {
+                output.push_str("</span>");
+            }
+        }
+        output.push_str("</pre>\n");
+
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            let mut cache = cache.lock().map_err(|e| anyhow!(e.to_string()))?;
+            cache.used.insert(key, output.clone());
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+impl Highlighter {
+    /// A highlighter with syntect's default syntaxes and theme, or without syntect at all (as
+    /// with `--no-highlight`), which doesn't need a site to load them from
+    pub fn for_tests(highlight: bool) -> Self {
+        let syntect = highlight.then(|| Syntect {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme_set: ThemeSet::load_defaults(),
+            theme: DEFAULT_THEME.to_string(),
+            theme_hash: String::new(),
+            classes: false,
+        });
+
+        Self {
+            syntect,
+            default_language: None,
+            warnings: Warnings::default(),
+            cache: None,
+        }
+    }
+}
+
+/// Highlights one line of a code block at a time, with either inline styles or classes
+enum Lines<'a> {
+    Styled(HighlightLines<'a>, String, syntect::highlighting::Color),
+    Classed(ClassedLines),
+}
+
+/// Highlighting with classes leaves spans open across lines (e.g. in block comments), so
+/// each line reopens the spans still open from the line before, and closes them again at
+/// its end. That way every line is balanced HTML, which can be wrapped for line numbers
+/// and diff classes the same way styled lines are.
+struct ClassedLines {
+    parse_state: ParseState,
+    scope_stack: ScopeStack,
+}
+
+impl ClassedLines {
+    fn new(syntax: &SyntaxReference) -> Self {
+        Self {
+            parse_state: ParseState::new(syntax),
+            scope_stack: ScopeStack::new(),
+        }
+    }
+
+    fn highlight_line(&mut self, line: &str, syntax_set: &SyntaxSet) -> anyhow::Result<String> {
+        let reopen = self
+            .scope_stack
+            .as_slice()
+            .iter()
+            .map(|scope| (0, ScopeStackOp::Push(*scope)))
+            .collect::<Vec<_>>();
+        let (mut html, open) =
+            line_tokens_to_classed_spans("", &reopen, CLASS_STYLE, &mut ScopeStack::new())?;
+
+        let ops = self.parse_state.parse_line(line, syntax_set)?;
+        let (spans, delta) =
+            line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut self.scope_stack)?;
+        html.push_str(&spans);
+
+        for _ in 0..open + delta {
+            html.push_str("</span>");
+        }
+
+        Ok(html)
+    }
+}
+
+/// Added and removed lines in a diff get a class, so sites can style the whole line
+fn diff_line_class(line: &str) -> Option<&'static str> {
+    if line.starts_with("+++") || line.starts_with("---") {
+        None
+    } else if line.starts_with('+') {
+        Some("diff-add")
+    } else if line.starts_with('-') {
+        Some("diff-del")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hl_lines_takes_lines_and_ranges() {
+        let options = CodeBlockOptions::parse("hl_lines=1-3 5").unwrap();
+        assert_eq!(options.hl_lines, vec![(1, 3), (5, 5)]);
+    }
+
+    #[test]
+    fn hl_lines_continues_over_commas() {
+        let options = CodeBlockOptions::parse("rust,hl_lines=1-3,5,linenos").unwrap();
+        assert_eq!(options.lang, "rust");
+        assert_eq!(options.hl_lines, vec![(1, 3), (5, 5)]);
+        assert!(options.linenos);
+    }
+
+    #[test]
+    fn hl_lines_follows_language() {
+        let options = CodeBlockOptions::parse("python,hl_lines=2").unwrap();
+        assert_eq!(options.lang, "python");
+        assert_eq!(options.hl_lines, vec![(2, 2)]);
+    }
+
+    #[test]
+    fn hl_lines_marks_those_lines() {
+        let options = CodeBlockOptions::parse("python,linenos,hl_lines=1-2,4").unwrap();
+        let output = Highlighter::for_tests(true)
+            .highlight(&options, "a = 1\nb = 2\nc = 3\nd = 4\n")
+            .unwrap();
+
+        for line in 1..=4 {
+            let marked = format!(
+                "<span class=\"highlight-line\"><span class=\"lineno\">{}</span>",
+                line
+            );
+            assert_eq!(output.contains(&marked), line != 3, "line {}", line);
+        }
+    }
+
+    #[test]
+    fn diff_lines_get_classes() {
+        let highlighter = Highlighter::for_tests(true);
+        let options = CodeBlockOptions::parse("diff").unwrap();
+        let output = highlighter
+            .highlight(&options, "--- a.txt\n+++ b.txt\n same\n-old\n+new\n")
+            .unwrap();
+
+        assert_eq!(output.matches("<span class=\"diff-del\">").count(), 1);
+        assert_eq!(output.matches("<span class=\"diff-add\">").count(), 1);
+        assert!(highlighter.warnings.is_empty());
+    }
+
+    #[test]
+    fn unknown_languages_fall_back_to_plain_text() {
+        let highlighter = Highlighter::for_tests(true);
+        let options = CodeBlockOptions::parse("klingon").unwrap();
+        let output = highlighter.highlight(&options, "+not a diff\n").unwrap();
+
+        assert!(!output.contains("diff-add"));
+        assert!(output.contains("+not a diff"));
+        assert!(!highlighter.warnings.is_empty());
     }
 }
@@ -0,0 +1,37 @@
+use crate::{page::Page, Config};
+
+/// Words of each page's text included in its summary
+const SUMMARY_WORDS: usize = 50;
+
+/// A plain-text index of the site in the `llms.txt` format: a markdown list of every
+/// page's title and permalink, with its description or the start of its text.
+/// Taxonomy term pages and drafts are left out.
+pub fn llms_txt(config: &Config, pages: &[Page]) -> String {
+    let mut output = format!("# {}\n\n## Pages\n\n", config.title);
+
+    for page in pages.iter().filter(|p| p.taxonomy.is_none() && !p.draft) {
+        output.push_str(&format!("- [{}]({})", page.title, page.permalink));
+
+        let summary = if page.description.is_empty() {
+            summarise(&page.content_text)
+        } else {
+            page.description.clone()
+        };
+        if !summary.is_empty() {
+            output.push_str(&format!(": {}", summary));
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+fn summarise(text: &str) -> String {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    if words.len() > SUMMARY_WORDS {
+        format!("{}…", words[..SUMMARY_WORDS].join(" "))
+    } else {
+        words.join(" ")
+    }
+}
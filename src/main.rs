@@ -1,14 +1,23 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::{self, create_dir_all, remove_dir_all},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
     str::FromStr,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::Instant,
 };
 
-use chrono::Utc;
+use anyhow::{anyhow, Context as _};
+use chrono::{DateTime, Utc};
 use clap::Parser;
+use glob::Pattern;
 use page::Page;
+use rayon::prelude::*;
 use serde::{self, Deserialize, Serialize};
 use site::Site;
 use tera::Tera;
@@ -18,21 +27,43 @@ use walkdir::WalkDir;
 
 use crate::{
     functions::{
-        get_section::GetSection, get_taxonomy_url::GetTaxonomyURL, get_url::GetURL,
+        asset_exists::AssetExists,
+        get_page::GetPage,
+        get_pages_by_date::GetPagesByDate,
+        get_section::GetSection,
+        get_taxonomy_siblings::GetTaxonomySiblings,
+        get_taxonomy_url::GetTaxonomyURL,
+        get_url::GetURL,
+        load_data::{DataFiles, LoadData},
         markdown::Markdown,
     },
     highlighter::Highlighter,
-    markdown::render_content,
-    page::PartialPage,
+    markdown::{missing_alt_text, split_summary, strip_html, word_count, WORDS_PER_MINUTE},
+    page::{PageLink, PartialPage, TaxonomyLink},
+    redirect::redirect_html,
+    renderer::Renderers,
+    section::{pager_path, SectionLink},
+    warnings::Warnings,
 };
 
+mod book;
+mod feed;
 mod frontmatter;
 mod functions;
 mod highlighter;
+mod llms;
 mod markdown;
 mod page;
+mod redirect;
+mod renderer;
 mod section;
+mod serve;
 mod site;
+mod sitemap;
+mod template_graph;
+mod toc;
+mod warnings;
+mod watch;
 
 #[derive(Parser, Debug)]
 #[command(name = "Mini Static Site Generator")]
@@ -46,39 +77,334 @@ struct Args {
     local: bool,
     #[arg(long)]
     base_url: Option<Url>,
+    /// Exit with status 2 if the build produced any warnings
+    #[arg(long)]
+    fail_on_warning: bool,
+    /// Only build this content file (relative to the content directory), skipping static
+    /// files, taxonomies and feeds, and leaving the rest of the output untouched
+    #[arg(long)]
+    page: Option<PathBuf>,
+    /// Exit with status 3 if the build takes longer than this many seconds
+    #[arg(long, value_name = "SECS")]
+    max_build_time: Option<f64>,
+    /// Keep highlighted code blocks in this directory between builds. Safe to delete at any time.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+    /// Skip syntax highlighting, and emit code blocks as plain text
+    #[arg(long)]
+    no_highlight: bool,
+    /// Include pages marked as drafts in their frontmatter
+    #[arg(long)]
+    drafts: bool,
+    /// After building, watch the site and rebuild whenever it changes
+    #[arg(long)]
+    watch: bool,
+    /// After building, serve the output directory at http://127.0.0.1:1111 until interrupted.
+    /// Implies `--local`, so that links stay on the server. With `--watch`, pages reload
+    /// themselves after each rebuild.
+    #[arg(long)]
+    serve: bool,
+    /// Build the content in this directory (relative to the site) instead of `content`
+    #[arg(long, default_value = "content")]
+    content: PathBuf,
+    /// Process pages in an order shuffled by this seed, to catch templates that depend on
+    /// the order pages happen to be found in. Output should be the same for every seed.
+    #[arg(long, hide = true, value_name = "N")]
+    shuffle_seed: Option<u64>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Port used by `--serve`, which `--local` links point to
+const SERVE_PORT: u16 = 1111;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     title: String,
     base_url: Url,
+    /// Base URL of the site's primary copy, for `page.canonical`, when it's built for a
+    /// mirror at `base_url`. Canonical URLs are the permalinks if this is unset.
+    canonical_base_url: Option<Url>,
     taxonomies: Vec<Taxonomy>,
+    /// Template used for pages that don't specify one in their frontmatter
+    #[serde(default = "default_template")]
+    default_template: String,
+    /// Language used to highlight code blocks that don't specify one
+    default_code_language: Option<String>,
+    /// Syntax highlighting theme, either one built into syntect or a `.tmTheme` file in
+    /// `highlight_themes/`, named after its file. Defaults to `base16-ocean.dark`.
+    highlight_theme: Option<String>,
+    /// Highlight code with classes instead of inline styles, and write the theme's styles
+    /// for them to `syntax.css`, for templates to link to
+    #[serde(default)]
+    highlight_css: bool,
+    /// Maximum number of items in feeds, or 0 for no limit. Sitemaps are never limited.
+    #[serde(default = "default_feed_limit")]
+    feed_limit: usize,
+    /// Sections (directories of `content/`) whose pages appear in the site's feeds,
+    /// e.g. `["blog", "news"]`. Pages from every section are included if this is unset.
+    feed_sections: Option<Vec<String>>,
+    /// Which of the built-in feeds to generate: `"rss"`, `"atom"` or `"both"`. A site's own
+    /// `rss.xml` or `atom.xml` template is always used in place of the built-in feed.
+    #[serde(default)]
+    feed_format: FeedFormat,
+    /// Indent the built-in feeds, one element per line, for reading and diffing them, rather
+    /// than writing each on a single line. Defaults to on for `--local` builds only.
+    pretty_feeds: Option<bool>,
+    /// Put each page's full content in the built-in feeds, rather than only its description.
+    /// Pages can override this with `feed_full_content` in their frontmatter.
+    #[serde(default)]
+    feed_full_content: bool,
+    /// Date pages that have no frontmatter `date` by their file modification time
+    #[serde(default)]
+    date_from_mtime: bool,
+    /// Name of a theme in `themes/`, whose templates and static files are used
+    /// wherever the site doesn't provide its own
+    theme: Option<String>,
+    /// Permissions for generated files on Unix, e.g. `output_mode = 0o644`
+    #[cfg_attr(not(unix), allow(dead_code))]
+    output_mode: Option<u32>,
+    /// Delimiters around shortcodes in content, e.g. `[[` and `]]` for sites that document
+    /// Tera or Jinja templates
+    #[serde(default = "default_shortcode_open")]
+    shortcode_open: String,
+    #[serde(default = "default_shortcode_close")]
+    shortcode_close: String,
+    /// Shortcodes whose output is block-level HTML (e.g. a `<figure>`), so it's always set
+    /// apart from the text around it rather than placed inside its paragraph. Shortcodes
+    /// with a `.md` template are always block-level.
+    #[serde(default)]
+    block_shortcodes: Vec<String>,
+    /// Marker that opens (as e.g. `:::note`) and closes an admonition container in content
+    #[serde(default = "default_admonition_marker")]
+    admonition_marker: String,
+    /// Container types rendered as admonitions. Containers of any other type are left as text.
+    #[serde(default = "default_admonition_types")]
+    admonition_types: Vec<String>,
+    /// Glob patterns for files to skip, relative to `content/` or the static directories
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Glob patterns for files to skip in `content/` only
+    #[serde(default)]
+    ignored_content: Vec<String>,
+    /// Glob patterns for files to skip in the static directories only
+    #[serde(default)]
+    ignored_static: Vec<String>,
+    /// Write an `llms.txt` listing every page with a plain-text summary
+    #[serde(default)]
+    llms_txt: bool,
+    /// Convert line endings in generated files to `\n`, and make sure each ends with one
+    #[serde(default)]
+    normalize_output: bool,
+    /// Render each markdown heading one level lower (`#` as `<h2>` and so on), for templates
+    /// that show the page title as the `<h1>`
+    #[serde(default)]
+    demote_headings: bool,
+    /// Start each heading with a link to itself, `<a class="anchor" href="#id"></a>`
+    #[serde(default)]
+    heading_anchors: bool,
+    /// Only give headings of this level and below anchor links, e.g. 2 to skip `<h1>`s
+    #[serde(default = "default_anchor_min_level")]
+    anchor_min_level: usize,
+    /// Alt text for images in content that have none (which are still warned about)
+    fallback_alt: Option<String>,
+    /// Markdown extensions, for page content and the `markdown` filter alike
+    #[serde(default)]
+    markdown: MarkdownConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    #[default]
+    Rss,
+    Atom,
+    Both,
+}
+
+/// The `[markdown]` table of `config.toml`. Everything but `smart_punctuation` is on unless
+/// turned off.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    tables: bool,
+    footnotes: bool,
+    strikethrough: bool,
+    tasklists: bool,
+    /// Curly quotes, en and em dashes for `--` and `---`, and an ellipsis for `...`
+    smart_punctuation: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: false,
+        }
+    }
+}
+
+impl MarkdownConfig {
+    pub fn options(&self) -> pulldown_cmark::Options {
+        let mut options = pulldown_cmark::Options::empty();
+        options.set(pulldown_cmark::Options::ENABLE_TABLES, self.tables);
+        options.set(pulldown_cmark::Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(
+            pulldown_cmark::Options::ENABLE_STRIKETHROUGH,
+            self.strikethrough,
+        );
+        options.set(pulldown_cmark::Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(
+            pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION,
+            self.smart_punctuation,
+        );
+        options
+    }
+}
+
+fn default_template() -> String {
+    "page.html".to_string()
+}
+
+fn default_feed_limit() -> usize {
+    20
+}
+
+fn default_anchor_min_level() -> usize {
+    1
+}
+
+fn default_shortcode_open() -> String {
+    "{{".to_string()
+}
+
+fn default_shortcode_close() -> String {
+    "}}".to_string()
+}
+
+fn default_admonition_marker() -> String {
+    ":::".to_string()
+}
+
+fn default_admonition_types() -> Vec<String> {
+    ["note", "tip", "info", "warning", "danger"]
+        .map(str::to_string)
+        .to_vec()
 }
 
 impl Config {
+    /// Url::join replaces the last path segment unless the base ends in a slash, which would
+    /// silently drop e.g. the `docs` from `https://example.com/docs`, so make sure they do
+    fn normalize_base_urls(&mut self) {
+        for url in [Some(&mut self.base_url), self.canonical_base_url.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            if !url.path().ends_with('/') {
+                let path = format!("{}/", url.path());
+                url.set_path(&path);
+            }
+        }
+    }
+
+    /// The absolute URL of a site-relative path, slugified the same way output paths are. All URLs
+    /// pointing into the site should be built here (or with `make_static_url`), so that they
+    /// agree with each other and with the output.
+    pub fn make_url(&self, path: &str) -> Url {
+        let (path, rest) = split_url_suffix(path.trim());
+        self.make_static_url(&format!("{}{}", slugify(path), rest))
+    }
+
+    /// The absolute URL of a site-relative path that is used verbatim, such as a static file.
+    /// A leading `/` is relative to `base_url`, so sites under a sub-path keep their prefix,
+    /// and absolute URLs are returned unchanged.
+    pub fn make_static_url(&self, path: &str) -> Url {
+        let path = path.trim();
+        if let Ok(url) = Url::parse(path) {
+            return url;
+        }
+        self.base_url.join(path.trim_start_matches('/')).unwrap()
+    }
+
     pub fn make_permalink(&self, path: &str) -> Url {
         let escaped = path.strip_suffix("index.html").unwrap_or(path);
-        self.base_url.join(escaped).unwrap()
+        self.make_url(escaped)
+    }
+
+    /// The canonical URL of a permalink, which is the same path under `canonical_base_url`
+    pub fn make_canonical(&self, permalink: &Url) -> Url {
+        let path = match permalink.as_str().strip_prefix(self.base_url.as_str()) {
+            Some(path) => path,
+            None => return permalink.clone(),
+        };
+
+        match &self.canonical_base_url {
+            Some(canonical_base_url) => canonical_base_url.join(path).unwrap(),
+            None => permalink.clone(),
+        }
+    }
+
+    /// Pick the pages for the site's feeds from a newest-first list of dated pages,
+    /// keeping only those in `feed_sections`, capped to the configured feed length
+    pub fn feed_pages(&self, pages: &[Page]) -> Vec<Page> {
+        let limit = if self.feed_limit == 0 {
+            usize::MAX
+        } else {
+            self.feed_limit
+        };
+
+        pages
+            .iter()
+            .filter(|page| match &self.feed_sections {
+                Some(sections) => sections
+                    .iter()
+                    .any(|section| Path::new(&page.name).starts_with(slugify(section))),
+                None => true,
+            })
+            .take(limit)
+            .cloned()
+            .collect()
     }
 }
 
 pub struct Context {
     home: PathBuf,
+    content_dir: PathBuf,
     output_dir: PathBuf,
     config: Config,
+    warnings: Warnings,
+    no_highlight: bool,
+    drafts: bool,
+    ignored_content: Vec<Pattern>,
+    ignored_static: Vec<Pattern>,
+    functions: BTreeSet<&'static str>,
 }
 
 impl Context {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         home: PathBuf,
+        content: &Path,
         output_dir: PathBuf,
         local: bool,
         base_url: &Option<Url>,
+        no_highlight: bool,
+        drafts: bool,
+        warnings: Warnings,
     ) -> anyhow::Result<Self> {
         let config_file = home.join("config.toml");
         let config_text = fs::read_to_string(config_file)?;
         let mut config: Config = toml::from_str(&config_text)?;
 
+        let content_dir = home.join(content);
+        if !content_dir.is_dir() {
+            return Err(anyhow!(
+                "content directory {} not found",
+                content_dir.display()
+            ));
+        }
+
         println!("config: {:?}", config);
 
         if let Some(url) = base_url {
@@ -86,21 +412,74 @@ impl Context {
         }
 
         if local {
-            config.base_url = Url::from_str("http://127.0.0.1:1111")?;
+            config.base_url = Url::from_str(&format!("http://127.0.0.1:{}", SERVE_PORT))?;
+        }
+        config.pretty_feeds.get_or_insert(local);
+
+        if config.shortcode_open.is_empty() || config.shortcode_close.is_empty() {
+            return Err(anyhow!("shortcode delimiters can't be empty"));
+        }
+
+        if config.admonition_marker.is_empty() {
+            return Err(anyhow!("admonition_marker can't be empty"));
+        }
+
+        if let Some(theme) = &config.theme {
+            let theme_dir = home.join("themes").join(theme);
+            if !theme_dir.is_dir() {
+                return Err(anyhow!(
+                    "theme '{}' not found in {}",
+                    theme,
+                    theme_dir.display()
+                ));
+            }
         }
 
+        config.normalize_base_urls();
+
+        let compile = |patterns: &[String]| {
+            config
+                .ignore
+                .iter()
+                .chain(patterns)
+                .map(|p| Pattern::new(p).with_context(|| format!("invalid ignore pattern '{}'", p)))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let ignored_content = compile(&config.ignored_content)?;
+        let ignored_static = compile(&config.ignored_static)?;
+
         Ok(Self {
             home,
+            content_dir,
             output_dir,
             config,
+            warnings,
+            no_highlight,
+            drafts,
+            ignored_content,
+            ignored_static,
+            // Tera registers these itself
+            functions: BTreeSet::from(["get_env", "get_random", "now", "range", "throw"]),
         })
     }
 
+    /// Register a template function, keeping track of its name to list when a template
+    /// calls one that doesn't exist
+    fn register_function<F: tera::Function + 'static>(
+        &mut self,
+        tera: &mut Tera,
+        name: &'static str,
+        function: F,
+    ) {
+        tera.register_function(name, function);
+        self.functions.insert(name);
+    }
+
     fn clean_output_dir(&self) -> anyhow::Result<()> {
         if self.output_dir.exists() {
             remove_dir_all(&self.output_dir)?;
-            create_dir_all(&self.output_dir)?;
         }
+        create_dir_all(&self.output_dir)?;
         Ok(())
     }
 
@@ -108,36 +487,110 @@ impl Context {
         self.home.join(path.as_ref())
     }
 
+    fn theme_dir(&self) -> Option<PathBuf> {
+        self.config
+            .theme
+            .as_ref()
+            .map(|theme| self.absolute("themes").join(theme))
+    }
+
+    /// Directories of static files, highest priority first
+    fn static_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.absolute("static")];
+        dirs.extend(self.theme_dir().map(|theme| theme.join("static")));
+        dirs
+    }
+
+    fn is_ignored_content(&self, relative: &Path) -> bool {
+        self.ignored_content
+            .iter()
+            .any(|p| p.matches_path(relative))
+    }
+
+    fn is_ignored_static(&self, relative: &Path) -> bool {
+        self.ignored_static.iter().any(|p| p.matches_path(relative))
+    }
+
     fn relative(&self, path: &Path) -> anyhow::Result<PathBuf> {
         Ok(path.strip_prefix(&self.home)?.into())
     }
 
-    fn create_output_dir(&self, path: &Path) -> anyhow::Result<()> {
+    /// Make way for a file in the output directory, after checking that `path` (which may
+    /// come from frontmatter) can't escape it, via `..` or a symlinked directory
+    fn output_file(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(anyhow!(
+                "refusing to write {}, which is outside the output directory",
+                path.display()
+            ));
+        }
+
+        fs::create_dir_all(&self.output_dir)?;
         let output = self.output_dir.join(path);
-        Ok(fs::create_dir_all(output)?)
+        let parent = output.parent().unwrap_or(&self.output_dir);
+
+        // check where the directories that already exist lead before creating any more, so
+        // nothing is created through a symlink either
+        let existing = parent
+            .ancestors()
+            .find(|dir| dir.symlink_metadata().is_ok())
+            .unwrap_or(&self.output_dir);
+        if !existing
+            .canonicalize()?
+            .starts_with(self.output_dir.canonicalize()?)
+        {
+            return Err(anyhow!(
+                "refusing to write {}, which is outside the output directory",
+                path.display()
+            ));
+        }
+
+        fs::create_dir_all(parent)?;
+
+        Ok(output)
     }
 
     fn copy_to_output(&self, file: &Path, path: &Path) -> anyhow::Result<()> {
-        path.parent()
-            .map(|p| self.create_output_dir(p))
-            .transpose()?;
+        let output = self.output_file(path)?;
 
-        let output = self.output_dir.join(path);
+        fs::copy(file, &output)?;
 
-        fs::copy(file, output)?;
-
-        Ok(())
+        self.set_output_mode(&output)
     }
 
     fn write_to_output(&self, path: &Path, contents: &str) -> anyhow::Result<()> {
-        path.parent()
-            .map(|p| self.create_output_dir(p))
-            .transpose()?;
+        let output = self.output_file(path)?;
 
-        let output = self.output_dir.join(path);
+        let mut normalized;
+        let contents = if self.config.normalize_output {
+            normalized = contents.replace("\r\n", "\n").replace('\r', "\n");
+            if !normalized.is_empty() && !normalized.ends_with('\n') {
+                normalized.push('\n');
+            }
+            &normalized
+        } else {
+            contents
+        };
 
-        fs::write(output, contents)?;
+        fs::write(&output, contents)?;
 
+        self.set_output_mode(&output)
+    }
+
+    #[cfg(unix)]
+    fn set_output_mode(&self, output: &Path) -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(mode) = self.config.output_mode {
+            fs::set_permissions(output, fs::Permissions::from_mode(mode))?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_output_mode(&self, _output: &Path) -> anyhow::Result<()> {
+        // file modes are a Unix concept, so output_mode is ignored elsewhere
         Ok(())
     }
 }
@@ -149,17 +602,86 @@ struct FrontMatter {
     template: Option<String>,
     description: Option<String>,
     taxonomies: Option<HashMap<String, Vec<String>>>,
+    extra: Option<toml::Table>,
+    /// Emit a redirect to this URL instead of rendering the page
+    redirect_to: Option<String>,
+    /// Sort the `pages` this page lists by `title`, `date`, `permalink`, `path` or an `extra.*`
+    /// field, rather than newest first
+    sort_by: Option<String>,
+    /// Reverse the order of the `pages` this page lists
+    reverse: Option<bool>,
+    /// Drafts are left out of the build, unless `--drafts` is passed
+    draft: Option<bool>,
+    /// Split the page into chapters at each heading of this level (only `"h1"` is supported)
+    split_on: Option<String>,
+    /// Last segment of the page's URL, in place of the one derived from its filename
+    slug: Option<String>,
+    /// Other paths (relative to the site root) that redirect to this page, such as its
+    /// URLs before it was moved
+    aliases: Option<Vec<String>>,
+    /// Also write this page to `page/2/` and so on under its directory, once for each page
+    /// of its section's listing when split into pages of this many
+    paginate_by: Option<usize>,
+    /// Markdown for the page's summary, used instead of the content up to `<!-- more -->`
+    summary: Option<String>,
+    /// Write the page to `a/b.html` rather than `a/b/index.html`, for hosting that can't
+    /// serve directory indexes
+    ugly_url: Option<bool>,
+    /// Override the site's `feed_full_content` for this page
+    feed_full_content: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Taxonomy {
     name: String,
+    /// URL segment for the taxonomy's pages, if it should differ from `name`, e.g. to
+    /// serve `categories` from `/topics/`. Frontmatter always uses `name`.
+    slug: Option<String>,
+}
+
+impl Taxonomy {
+    fn slug(&self) -> &str {
+        self.slug.as_deref().unwrap_or(&self.name)
+    }
+
+    fn term_template_name(&self) -> String {
+        format!("{}/single.html", &self.name)
+    }
+
+    /// The template for the page listing all of the taxonomy's terms, which is only
+    /// rendered if the site has it
+    fn list_template_name(&self) -> String {
+        format!("{}/list.html", &self.name)
+    }
+
+    fn list_output_path(&self) -> String {
+        format!("{}/index.html", slugify(self.slug()))
+    }
+
+    /// The output path of the page listing everything tagged with `term`
+    fn term_output_path(&self, term: &str) -> String {
+        output_path(
+            &Path::new(self.slug()).join(term),
+            Some(&self.term_template_name()),
+            false,
+        )
+    }
 }
 
 fn setup_template_engine(context: &Context) -> anyhow::Result<Tera> {
-    let template_dir = context.absolute("templates");
+    let template_glob = |dir: PathBuf| dir.join("templates").join("**").join("*");
 
-    let tera = Tera::new(&template_dir.join("**").join("*").to_string_lossy())?;
+    let mut tera = Tera::parse(&template_glob(context.home.clone()).to_string_lossy())?;
+
+    // templates already loaded from the site take precedence over the theme's
+    if let Some(theme_dir) = context.theme_dir() {
+        let theme = Tera::parse(&template_glob(theme_dir).to_string_lossy())?;
+        tera.extend(&theme)?;
+    } else {
+        tera.build_inheritance_chains()?;
+    }
+
+    template_graph::check_cycles(&tera, &context.warnings)?;
 
     println!(
         "loaded templates: {:?}",
@@ -169,14 +691,75 @@ fn setup_template_engine(context: &Context) -> anyhow::Result<Tera> {
     Ok(tera)
 }
 
-fn output_path(relative_path: &Path, template_name: Option<&str>) -> String {
+/// Template names without an extension are taken to be HTML, so `post` means `post.html`
+fn template_with_extension(template_name: &str) -> String {
+    if Path::new(template_name).extension().is_some() {
+        template_name.to_string()
+    } else {
+        format!("{}.html", template_name)
+    }
+}
+
+/// Whether a content file stands for the directory that contains it, i.e. is an `index.md`
+/// or `_index.md`. Only the file stem is considered, so this holds at any depth, including
+/// the root of `content/`.
+fn is_index(relative_path: &Path) -> bool {
+    relative_path
+        .file_stem()
+        .is_some_and(|stem| stem == "index" || stem == "_index")
+}
+
+/// Replace the segment of a content file's path that becomes the last segment of its URL:
+/// the file stem, or the directory name for an index file
+fn with_slug(relative_path: &Path, slug: &str) -> anyhow::Result<PathBuf> {
+    let slug = slugify(slug);
+    if slug.contains('/') || slug.trim_matches('.').is_empty() {
+        return Err(anyhow!("slug '{}' isn't a single URL segment", slug));
+    }
+
+    let file_name = relative_path.file_name().unwrap_or_default();
+
+    Ok(if is_index(relative_path) {
+        let dir = relative_path.parent().unwrap_or(Path::new(""));
+        dir.with_file_name(slug).join(file_name)
+    } else {
+        match relative_path.extension() {
+            Some(extension) => {
+                relative_path.with_file_name(format!("{}.{}", slug, extension.to_string_lossy()))
+            }
+            None => relative_path.with_file_name(slug),
+        }
+    })
+}
+
+/// Where a file copied verbatim from `content/` is written, relative to the output directory.
+/// Its directories are slugified like page URLs are, so assets stay next to the pages that
+/// link to them relatively, but the file name is kept as it is.
+fn asset_output_path(relative_path: &Path) -> PathBuf {
+    let file_name = relative_path.file_name().unwrap_or_default();
+    match relative_path.parent().and_then(Path::to_str) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(slugify(dir)).join(file_name),
+        _ => PathBuf::from(file_name),
+    }
+}
+
+/// Where a content file is written, relative to the output directory. With an HTML template,
+/// `a/b.md` becomes `a/b/index.html` (or `a/b.html` with `ugly_url`), and an index file
+/// becomes its directory's `index.html` (so `index.md` or an empty path is written to the root
+/// `index.html`). With any other template the file keeps its path, and takes the template's
+/// extension.
+fn output_path(relative_path: &Path, template_name: Option<&str>, ugly_url: bool) -> String {
     let mut output_path = relative_path.with_extension("");
     if let Some(extension) = Path::new(template_name.unwrap_or("")).extension() {
         if extension.eq("html") {
-            if output_path.file_name().map(|n| n.eq("index")).unwrap() {
+            if is_index(relative_path) {
                 output_path.pop();
+                output_path = output_path.join("index.html");
+            } else if ugly_url {
+                output_path.as_mut_os_string().push(".html");
+            } else {
+                output_path = output_path.join("index.html");
             }
-            output_path = output_path.join("index.html");
         } else {
             output_path = output_path.with_extension(extension);
         }
@@ -186,128 +769,523 @@ fn output_path(relative_path: &Path, template_name: Option<&str>) -> String {
 }
 
 fn copy_static_files(context: &Context) -> anyhow::Result<()> {
-    let static_dir: PathBuf = context.absolute("static");
-
-    for entry in WalkDir::new(&static_dir) {
-        let entry = entry?;
-
-        if !entry.file_type().is_file() {
+    // copy in reverse priority order, so the site's own files overwrite the theme's
+    for static_dir in context.static_dirs().iter().rev() {
+        if !static_dir.exists() {
             continue;
         }
 
-        context.copy_to_output(entry.path(), entry.path().strip_prefix(&static_dir)?)?;
+        let walker = WalkDir::new(static_dir).into_iter().filter_entry(|e| {
+            e.path()
+                .strip_prefix(static_dir)
+                .map_or(true, |p| !context.is_ignored_static(p))
+        });
+
+        for entry in walker {
+            let entry = entry?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            context.copy_to_output(entry.path(), entry.path().strip_prefix(static_dir)?)?;
+        }
     }
 
     Ok(())
 }
 
+/// What a single content file contributes to the site
+#[derive(Default)]
+struct ProcessedFile {
+    section: Option<(String, SectionLink)>,
+    pages: Vec<Page>,
+    /// Output paths of redirect stubs, written for aliases or in place of a page
+    redirects: Vec<String>,
+}
+
+/// Template for the index pages of sections, made from `_index.md`, that don't specify one
+const SECTION_TEMPLATE: &str = "section.html";
+
+/// Files in `content/` that are copied to the output as they are
+const STATIC_FILE_EXTENSIONS: [&str; 5] = ["png", "webp", "jpg", "jpeg", "gif"];
+
 fn process_templated_files(
     context: &Context,
     tera: &Tera,
     highlighter: &Highlighter,
+    renderers: &Renderers,
+    only_page: Option<&Path>,
+    shuffle_seed: Option<u64>,
 ) -> anyhow::Result<Site> {
-    let static_file_extensions = HashSet::from(["png", "webp", "jpg", "jpeg", "gif", "gif"]);
+    let content_dir = &context.content_dir;
+
+    let walker = WalkDir::new(only_page.unwrap_or(content_dir))
+        .into_iter()
+        .filter_entry(|e| {
+            e.path()
+                .strip_prefix(content_dir)
+                .map_or(true, |p| !context.is_ignored_content(p))
+        });
+
+    // walking the content is cheap, but compiling it isn't, so that's done in parallel
+    let mut files = vec![];
+    for entry in walker {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+
+    if let Some(seed) = shuffle_seed {
+        shuffle(&mut files, seed);
+    }
+
+    let processed = files
+        .par_iter()
+        .map(|path| {
+            process_templated_file(context, tera, highlighter, renderers, content_dir, path)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
     let mut site = Site::new();
+    let mut sources: HashMap<String, &Path> = HashMap::new();
 
-    let content_dir: PathBuf = context.absolute("content");
+    for (path, file) in files.iter().zip(processed) {
+        if let Some((dir, section)) = file.section {
+            site.sections.insert(dir, section);
+        }
 
-    for entry in WalkDir::new(&content_dir) {
-        let entry = entry?;
+        let outputs = file
+            .pages
+            .iter()
+            .map(|page| &page.name)
+            .chain(&file.redirects);
+
+        for output in outputs {
+            if let Some(other) = sources.insert(output.clone(), path) {
+                return Err(anyhow!(
+                    "{} and {} would both be written to {}",
+                    context.relative(other)?.display(),
+                    context.relative(path)?.display(),
+                    output
+                ));
+            }
+        }
 
-        if !entry.file_type().is_file() {
-            continue;
+        for page in file.pages {
+            site.pages.insert(page.name.clone(), page);
         }
+    }
 
-        if let Some(extension) = entry.path().extension() {
-            if static_file_extensions.contains(extension.to_str().unwrap_or("")) {
-                println!(
-                    "copying {} to output",
-                    context.relative(entry.path())?.display()
-                );
+    // every section has to be known before any page's ancestors can be
+    for page in site.pages.values_mut() {
+        page.ancestors = ancestors(context, &site.sections, &page.name);
+    }
 
-                context.copy_to_output(entry.path(), entry.path().strip_prefix(&content_dir)?)?;
-                continue;
+    // and every page before the number of pages in a paginated listing is
+    for page in site.pages.values() {
+        for current_page in 2..=pager_count(&site, page) {
+            let output = pager_output_path(page, current_page);
+            if let Some(other) = sources.get(&output) {
+                return Err(anyhow!(
+                    "{} and page {} of {} would both be written to {}",
+                    context.relative(other)?.display(),
+                    current_page,
+                    context.relative(sources[&page.name])?.display(),
+                    output
+                ));
             }
         }
+    }
+
+    Ok(site)
+}
 
-        println!("compiling {}", context.relative(entry.path())?.display());
+/// The sections containing a page, from the root down, for breadcrumbs. Sections without an
+/// `_index.md` are named after their directory, and the root after the site.
+fn ancestors(
+    context: &Context,
+    sections: &HashMap<String, SectionLink>,
+    name: &str,
+) -> Vec<SectionLink> {
+    // `blog/post/index.html`, `blog/post.html` and `blog/feed.xml` are all in `blog`
+    let location = name.strip_suffix("index.html").unwrap_or(name);
+    let parent = match Path::new(location.trim_end_matches('/')).parent() {
+        Some(parent) => parent,
+        None => return vec![],
+    };
+
+    let mut dirs = parent.ancestors().collect::<Vec<_>>();
+    dirs.reverse();
+
+    dirs.into_iter()
+        .map(|dir| {
+            let dir = dir.to_string_lossy();
+            if dir.is_empty() {
+                return SectionLink {
+                    title: context.config.title.clone(),
+                    permalink: context.config.base_url.clone(),
+                };
+            }
 
-        let contents = fs::read_to_string(entry.path())?;
+            if let Some(section) = sections.get(dir.as_ref()) {
+                return section.clone();
+            }
 
-        let (frontmatter, body) = frontmatter::parse::<FrontMatter>(&contents)?;
+            let segment = dir.rsplit('/').next().unwrap_or_default();
+            let mut title = segment.replace(['-', '_'], " ");
+            if let Some(first) = title.get(..1) {
+                title.replace_range(..1, &first.to_uppercase());
+            }
 
-        if let Some(filename) = entry.path().file_name() {
-            if filename.to_string_lossy().starts_with('_') {
-                continue;
+            SectionLink {
+                title,
+                permalink: context.config.make_permalink(&format!("{}/", dir)),
             }
+        })
+        .collect()
+}
+
+fn process_templated_file(
+    context: &Context,
+    tera: &Tera,
+    highlighter: &Highlighter,
+    renderers: &Renderers,
+    content_dir: &Path,
+    path: &Path,
+) -> anyhow::Result<ProcessedFile> {
+    let mut processed = ProcessedFile::default();
+
+    if let Some(extension) = path.extension() {
+        if STATIC_FILE_EXTENSIONS.contains(&extension.to_str().unwrap_or("")) {
+            println!("copying {} to output", context.relative(path)?.display());
+
+            context.copy_to_output(path, &asset_output_path(path.strip_prefix(content_dir)?))?;
+            return Ok(processed);
         }
+    }
 
-        let template_name = frontmatter.template.as_deref().unwrap_or("page.html");
+    let contents = match String::from_utf8(fs::read(path)?) {
+        Ok(contents) if !contents.contains('\0') => contents,
+        _ => {
+            context.warnings.warn(
+                "binary content",
+                format!(
+                    "{} doesn't look like a text file, copying it verbatim",
+                    context.relative(path)?.display()
+                ),
+            );
+
+            context.copy_to_output(path, &asset_output_path(path.strip_prefix(content_dir)?))?;
+            return Ok(processed);
+        }
+    };
+
+    // `content/{taxonomy}/{term}.md` describes a term page, see process_taxonomies
+    let relative = path.strip_prefix(content_dir)?;
+    let is_term_description = relative.parent().is_some_and(|parent| {
+        context
+            .config
+            .taxonomies
+            .iter()
+            .any(|t| parent == Path::new(&t.name))
+    });
+    let is_private = relative
+        .file_name()
+        .is_some_and(|f| f.to_string_lossy().starts_with('_'));
+    if is_term_description && !is_private {
+        return Ok(processed);
+    }
 
-        let output_path = output_path(
-            entry.path().strip_prefix(&content_dir)?,
-            Some(template_name),
-        );
+    println!("compiling {}", context.relative(path)?.display());
+
+    let (frontmatter, body) = frontmatter::parse::<FrontMatter>(&contents)?;
+
+    let mut section_dir = None;
+
+    if path.file_stem().map(|s| s.eq("_index")).unwrap_or(false) {
+        let dir = path
+            .parent()
+            .unwrap()
+            .strip_prefix(content_dir)?
+            .to_string_lossy()
+            .to_string();
+        let dir = slugify(&dir);
+
+        let title = frontmatter.title.clone().unwrap_or_else(|| {
+            dir.rsplit('/')
+                .next()
+                .map(str::to_string)
+                .unwrap_or_default()
+        });
+        let permalink = if dir.is_empty() {
+            context.config.base_url.clone()
+        } else {
+            context.config.make_permalink(&format!("{}/", dir))
+        };
 
-        let permalink = context.config.make_permalink(&output_path);
+        processed.section = Some((dir.clone(), SectionLink { title, permalink }));
+
+        // sites without a section template only use `_index.md` to name the section
+        let has_template = frontmatter.template.is_some()
+            || tera.get_template_names().any(|t| t == SECTION_TEMPLATE);
+        if !has_template {
+            if !body.trim().is_empty() {
+                context.warnings.warn(
+                    "missing section template",
+                    format!(
+                        "{} has content, but there's no {} template to render it with",
+                        context.relative(path)?.display(),
+                        SECTION_TEMPLATE
+                    ),
+                );
+            }
+            return Ok(processed);
+        }
 
-        let name = output_path.to_string();
-        let taxonomies = frontmatter.taxonomies.unwrap_or_default();
+        section_dir = Some(dir);
+    } else if let Some(filename) = path.file_name() {
+        // other files starting with `_` are partials
+        if filename.to_string_lossy().starts_with('_') {
+            return Ok(processed);
+        }
+    }
 
-        let partial = PartialPage {
-            title: frontmatter.title.unwrap_or(
-                entry
-                    .path()
-                    .file_stem()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string(),
-            ),
-            date: frontmatter.date.and_then(|d| d.date).map(|d| d.to_string()),
-            description: frontmatter.description.unwrap_or_default(),
-            permalink,
-        };
+    let draft = frontmatter.draft.unwrap_or(false);
+    if draft && !context.drafts {
+        println!("skipping draft {}", context.relative(path)?.display());
+        return Ok(processed);
+    }
 
-        let mut summary = None;
-
-        if let Some(start) = body.find("<!--") {
-            if let Some(end) = body[start + 4..].find("-->") {
-                if body[start + 4..start + 4 + end]
-                    .trim()
-                    .eq_ignore_ascii_case("more")
-                {
-                    summary = Some(render_content(
-                        &body[0..start],
-                        &partial,
-                        tera,
-                        highlighter,
-                    )?);
-                }
+    let template_name = template_with_extension(frontmatter.template.as_deref().unwrap_or(
+        match section_dir {
+            Some(_) => SECTION_TEMPLATE,
+            None => &context.config.default_template,
+        },
+    ));
+    let template_name = template_name.as_str();
+
+    let relative = match &frontmatter.slug {
+        Some(slug) => with_slug(relative, slug)
+            .with_context(|| format!("in {}", context.relative(path).unwrap().display()))?,
+        None => relative.to_path_buf(),
+    };
+
+    let ugly_url = frontmatter.ugly_url.unwrap_or(false);
+    let output_path = output_path(&relative, Some(template_name), ugly_url);
+
+    if let Some(target) = &frontmatter.redirect_to {
+        let target = context.config.base_url.join(target).with_context(|| {
+            format!(
+                "invalid redirect_to '{}' in {}",
+                target,
+                context.relative(path).unwrap().display()
+            )
+        })?;
+
+        context.write_to_output(Path::new(&output_path), &redirect_html(&target))?;
+        processed.redirects.push(output_path);
+        return Ok(processed);
+    }
+
+    let taxonomies = frontmatter.taxonomies.unwrap_or_default();
+
+    for taxonomy in taxonomies.keys() {
+        if !context
+            .config
+            .taxonomies
+            .iter()
+            .any(|t| &t.name == taxonomy)
+        {
+            context.warnings.warn(
+                "undeclared taxonomies",
+                format!(
+                    "{} uses taxonomy '{}', which is not declared in config.toml",
+                    context.relative(path)?.display(),
+                    taxonomy
+                ),
+            );
+        }
+    }
+
+    let taxonomy_links: HashMap<_, _> = context
+        .config
+        .taxonomies
+        .iter()
+        .filter_map(|taxonomy| {
+            let terms = taxonomies.get(&taxonomy.name)?;
+            let links = terms
+                .iter()
+                .map(|term| TaxonomyLink {
+                    name: term.clone(),
+                    permalink: context
+                        .config
+                        .make_permalink(&taxonomy.term_output_path(term)),
+                })
+                .collect();
+            Some((taxonomy.name.clone(), links))
+        })
+        .collect();
+
+    let mtime_date = if context.config.date_from_mtime {
+        let modified = fs::metadata(path)?.modified()?;
+        Some(DateTime::<Utc>::from(modified).date_naive().to_string())
+    } else {
+        None
+    };
+
+    let title = frontmatter
+        .title
+        .unwrap_or(path.file_stem().unwrap().to_string_lossy().to_string());
+    let date = frontmatter
+        .date
+        .and_then(|d| d.date)
+        .map(|d| d.to_string())
+        .or(mtime_date);
+    let description = frontmatter.description.unwrap_or_default();
+    let extra =
+        frontmatter::to_tera_value(toml::Value::Table(frontmatter.extra.unwrap_or_default()));
+
+    // the title, output path and markdown of each page built from this file
+    let mut parts = vec![(title, output_path, body)];
+
+    match frontmatter.split_on.as_deref() {
+        None => {}
+        Some("h1") => {
+            let (preamble, chapters) = book::split_chapters(body);
+            parts[0].2 = preamble;
+
+            let mut book_dir = relative.with_extension("");
+            if is_index(&relative) {
+                book_dir.pop();
             }
+
+            parts.extend(chapters.into_iter().map(|chapter| {
+                let path = crate::output_path(
+                    &book_dir.join(&chapter.slug),
+                    Some(template_name),
+                    ugly_url,
+                );
+                (chapter.title, path, chapter.body)
+            }));
         }
+        Some(other) => {
+            return Err(anyhow!(
+                "unsupported split_on '{}' in {}, only \"h1\" is supported",
+                other,
+                context.relative(path)?.display()
+            ))
+        }
+    }
 
-        let content = render_content(body, &partial, tera, highlighter)?;
+    let links = parts
+        .iter()
+        .map(|(title, output_path, _)| PageLink {
+            title: title.clone(),
+            permalink: context.config.make_permalink(output_path),
+        })
+        .collect::<Vec<_>>();
+
+    for (i, (title, output_path, body)) in parts.into_iter().enumerate() {
+        let partial = PartialPage {
+            name: output_path.clone(),
+            title,
+            // chapters aren't posts in their own right, so keep them out of dated listings
+            date: date.clone().filter(|_| i == 0),
+            description: description.clone(),
+            permalink: links[i].permalink.clone(),
+        };
+
+        let content =
+            renderers
+                .for_path(path)
+                .render(body, &partial, tera, highlighter, &context.config)?;
+        let content = check_alt_text(context, path, &content)?;
+        let (content, toc) = toc::heading_ids(&content, &context.config);
+        let summary = match &frontmatter.summary {
+            Some(summary) if i == 0 => Some(functions::markdown::render(
+                summary,
+                &context.config.markdown,
+            )),
+            _ => split_summary(&content, &partial.permalink),
+        };
+        let content_text = strip_html(&content);
+        let words = word_count(body, &context.config.markdown);
+
+        // chapters share the book's metadata, but only the book itself is tagged
+        let (taxonomies, taxonomy_links) = if i == 0 {
+            (taxonomies.clone(), taxonomy_links.clone())
+        } else {
+            Default::default()
+        };
 
         let page = Page {
-            name,
-            output_path: Path::new(&output_path).to_path_buf(),
+            name: partial.name,
+            sort_by: frontmatter.sort_by.clone(),
+            sort_reverse: frontmatter.reverse.unwrap_or(false),
+            // chapters of a book aren't indexes of anything
+            paginate_by: frontmatter.paginate_by.filter(|_| i == 0),
+            section: section_dir.clone().filter(|_| i == 0),
+            output_path: PathBuf::from(output_path),
             template_name: template_name.to_string(),
             title: partial.title,
             taxonomy: None,
             date: partial.date,
+            description_html: functions::markdown::render(
+                &partial.description,
+                &context.config.markdown,
+            ),
             description: partial.description,
-            permalink: partial.permalink.clone(),
+            canonical: context.config.make_canonical(&partial.permalink),
+            permalink: partial.permalink,
+            content_hash: Page::hash_content(&content),
             content,
             summary,
+            content_text,
+            word_count: words,
+            reading_time: words.div_ceil(WORDS_PER_MINUTE),
+            toc,
+            ancestors: vec![],
             // key: partial.permalink.into(),
             taxonomies,
+            taxonomy_links,
+            draft,
+            previous: i.checked_sub(1).map(|i| links[i].clone()),
+            next: links.get(i + 1).cloned(),
+            feed_full_content: frontmatter
+                .feed_full_content
+                .unwrap_or(context.config.feed_full_content),
+            extra: extra.clone(),
         };
 
-        site.pages.insert(page.name.clone(), page);
+        processed.pages.push(page);
     }
 
-    Ok(site)
+    for alias in frontmatter.aliases.iter().flatten() {
+        let alias_path = alias_output_path(alias);
+        context.write_to_output(Path::new(&alias_path), &redirect_html(&links[0].permalink))?;
+        processed.redirects.push(alias_path);
+    }
+
+    Ok(processed)
+}
+
+/// Where the redirect stub for an alias is written: the alias itself if it names an HTML
+/// file, or otherwise the `index.html` inside it
+fn alias_output_path(alias: &str) -> String {
+    let path = slugify(alias.trim().trim_matches('/'));
+    let is_html = Path::new(&path)
+        .extension()
+        .is_some_and(|e| e == "html" || e == "htm");
+
+    if is_html {
+        path
+    } else if path.is_empty() {
+        "index.html".to_string()
+    } else {
+        format!("{}/index.html", path)
+    }
 }
 
 fn render_page(
@@ -315,22 +1293,145 @@ fn render_page(
     tera: &Tera,
     page: &Page,
     pages: &Vec<Page>,
+    site: &Site,
+    current_page: usize,
 ) -> anyhow::Result<String> {
+    let mut sorted;
+    let pages = match &page.sort_by {
+        Some(key) => {
+            sorted = pages.clone();
+            page::sort_pages(&mut sorted, key, page.sort_reverse)
+                .with_context(|| format!("can't sort the pages listed on {}", page.name))?;
+            &sorted
+        }
+        None if page.sort_reverse => {
+            sorted = pages.clone();
+            sorted.reverse();
+            &sorted
+        }
+        None => pages,
+    };
+
     let mut ctx = tera::Context::new();
 
     ctx.insert("config", &context.config);
     ctx.insert("page", &page);
     ctx.insert("pages", &pages);
-    ctx.insert("current_url", &page.permalink);
+    ctx.insert("taxonomies", &site.taxonomies);
+    if let Some(dir) = &page.section {
+        ctx.insert("section", &site.section(dir));
+    }
+    ctx.insert("current_page", &current_page);
+    if current_page > 1 {
+        let url = context
+            .config
+            .make_url(&pager_path(&page_dir(page), current_page));
+        ctx.insert("current_url", &url);
+    } else {
+        ctx.insert("current_url", &page.permalink);
+    }
     ctx.insert("last_updated", &Utc::now().to_string());
 
-    Ok(tera.render(&page.template_name, &ctx)?)
+    tera.render(&page.template_name, &ctx)
+        .map_err(|err| match missing_function(&err) {
+            Some(name) => {
+                anyhow!(
+                    "template {} (rendering {}) calls function '{}', which doesn't exist. Available functions are: {}",
+                    page.template_name,
+                    page.name,
+                    name,
+                    context.functions.iter().copied().collect::<Vec<_>>().join(", ")
+                )
+            }
+            None => err.into(),
+        })
+}
+
+/// The name of the function a failed render tried to call, if the failure is
+/// because no function of that name is registered
+fn missing_function(err: &tera::Error) -> Option<&str> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+
+    while let Some(err) = source {
+        if let Some(tera::ErrorKind::FunctionNotFound(name)) =
+            err.downcast_ref::<tera::Error>().map(|e| &e.kind)
+        {
+            return Some(name);
+        }
+        source = err.source();
+    }
+
+    None
+}
+
+/// Shuffle `items` into an order that only depends on `seed` (a Fisher-Yates shuffle driven
+/// by splitmix64), for `--shuffle-seed`
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Warn about each image in a page's rendered content that has no alt text, and give it the
+/// site's `fallback_alt`, if it has one
+fn check_alt_text(context: &Context, source: &Path, content: &str) -> anyhow::Result<String> {
+    let (content, missing) = missing_alt_text(content, context.config.fallback_alt.as_deref());
+
+    for src in missing {
+        context.warnings.warn(
+            "missing alt text",
+            format!(
+                "{} has an image without alt text: {}",
+                context.relative(source)?.display(),
+                src
+            ),
+        );
+    }
+
+    Ok(content)
+}
+
+/// The directory a page is written to, which is the section a paginated page lists
+fn page_dir(page: &Page) -> String {
+    Path::new(&page.name)
+        .parent()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// How many pages a page's listing is split into with `paginate_by`
+fn pager_count(site: &Site, page: &Page) -> usize {
+    match page.paginate_by {
+        Some(paginate_by) if paginate_by > 0 => site
+            .section_pages(&page_dir(page))
+            .count()
+            .div_ceil(paginate_by)
+            .max(1),
+        _ => 1,
+    }
+}
+
+/// Where page `current_page` (from 2 on) of a paginated page is written
+fn pager_output_path(page: &Page, current_page: usize) -> String {
+    format!("{}index.html", pager_path(&page_dir(page), current_page))
 }
 
 fn render_pages_for_site(
     context: &Context,
     tera: &Tera,
     site: Arc<RwLock<Site>>,
+    include_site_templates: bool,
+    shuffle_seed: Option<u64>,
 ) -> anyhow::Result<()> {
     let site = site.try_read().unwrap();
 
@@ -340,12 +1441,17 @@ fn render_pages_for_site(
         .filter(|p| p.date.is_some())
         .cloned()
         .collect::<Vec<_>>();
+    // pages with the same date are otherwise left in whatever order the site's map has them
+    if let Some(seed) = shuffle_seed {
+        shuffle(&mut pages, seed);
+    }
     pages.sort_by_key(|p| p.date.clone().unwrap());
     pages.reverse();
 
     for page in site.pages.values() {
-        let contents = if let Some((taxonomy, term)) = &page.taxonomy {
-            let term_pages = pages
+        let term_pages;
+        let listed = if let Some((taxonomy, term)) = &page.taxonomy {
+            term_pages = pages
                 .iter()
                 .filter(|p| {
                     p.taxonomies.contains_key(taxonomy)
@@ -353,13 +1459,149 @@ fn render_pages_for_site(
                 })
                 .cloned()
                 .collect::<Vec<_>>();
+            &term_pages
+        } else {
+            &pages
+        };
+
+        for current_page in 1..=pager_count(&site, page) {
+            let contents = render_page(context, tera, page, listed, &site, current_page)?;
+
+            let output_path = if current_page == 1 {
+                page.output_path.clone()
+            } else {
+                PathBuf::from(pager_output_path(page, current_page))
+            };
+
+            context.write_to_output(&output_path, &contents)?;
+        }
+    }
+
+    let mut all_pages = site.pages.values().cloned().collect::<Vec<_>>();
+    all_pages.sort_by(|a, b| a.permalink.cmp(&b.permalink));
+
+    if include_site_templates {
+        render_taxonomy_lists(context, tera, &site)?;
+        render_site_templates(context, tera, &pages, &all_pages)?;
+    }
+
+    Ok(())
+}
+
+/// Site-wide templates that are rendered to the root of the output directory,
+/// if the user provides them. Feeds only receive the most recent `feed_limit` pages
+/// (from `feed_sections`, if set).
+const SITE_TEMPLATES: [&str; 3] = ["sitemap.xml", "rss.xml", "atom.xml"];
+const FEED_TEMPLATES: [&str; 2] = ["rss.xml", "atom.xml"];
+
+fn render_site_templates(
+    context: &Context,
+    tera: &Tera,
+    pages: &[Page],
+    all_pages: &Vec<Page>,
+) -> anyhow::Result<()> {
+    for template_name in SITE_TEMPLATES {
+        if !tera.get_template_names().any(|t| t == template_name) {
+            continue;
+        }
 
-            render_page(context, tera, page, &term_pages)?
+        let pages = if FEED_TEMPLATES.contains(&template_name) {
+            context.config.feed_pages(pages)
         } else {
-            render_page(context, tera, page, &pages)?
+            pages.to_vec()
         };
 
-        context.write_to_output(&page.output_path, &contents)?;
+        let mut ctx = tera::Context::new();
+
+        ctx.insert("config", &context.config);
+        ctx.insert("pages", &pages);
+        ctx.insert("all_pages", all_pages);
+        ctx.insert("last_updated", &Utc::now().to_string());
+
+        let contents = tera.render(template_name, &ctx)?;
+        context.write_to_output(Path::new(template_name), &contents)?;
+    }
+
+    // every site gets a sitemap and feeds, even if it doesn't provide templates for them
+    let has_template = |name: &str| tera.get_template_names().any(|t| t == name);
+
+    if !has_template("sitemap.xml") {
+        let contents = sitemap::sitemap(all_pages);
+        context.write_to_output(Path::new("sitemap.xml"), &contents)?;
+    }
+
+    let feed_pages = context.config.feed_pages(pages);
+    let feed_format = context.config.feed_format;
+
+    if matches!(feed_format, FeedFormat::Rss | FeedFormat::Both) && !has_template("rss.xml") {
+        let contents = feed::rss(&context.config, &feed_pages);
+        context.write_to_output(Path::new("rss.xml"), &contents)?;
+    }
+    if matches!(feed_format, FeedFormat::Atom | FeedFormat::Both) && !has_template("atom.xml") {
+        let contents = feed::atom(&context.config, &feed_pages);
+        context.write_to_output(Path::new("atom.xml"), &contents)?;
+    }
+
+    if context.config.llms_txt {
+        let contents = llms::llms_txt(&context.config, all_pages);
+        context.write_to_output(Path::new("llms.txt"), &contents)?;
+    }
+
+    Ok(())
+}
+
+/// A term on a taxonomy's list page
+#[derive(Serialize)]
+struct TermListing<'a> {
+    name: &'a str,
+    permalink: Url,
+    pages: &'a [Page],
+}
+
+/// Render each taxonomy's `{taxonomy}/list.html` template, if there is one, to the root of
+/// the taxonomy with its `terms` (sorted by name, each with its pages)
+fn render_taxonomy_lists(context: &Context, tera: &Tera, site: &Site) -> anyhow::Result<()> {
+    for taxonomy in &context.config.taxonomies {
+        let template_name = taxonomy.list_template_name();
+        if !tera.get_template_names().any(|t| t == template_name) {
+            continue;
+        }
+
+        let output_path = taxonomy.list_output_path();
+        if let Some(existing) = site.pages.get(&output_path) {
+            return Err(anyhow!(
+                "the list of taxonomy '{}' and the page '{}' would both be written to {}",
+                taxonomy.name,
+                existing.title,
+                output_path
+            ));
+        }
+
+        let terms = site
+            .taxonomies
+            .get(&taxonomy.name)
+            .into_iter()
+            .flatten()
+            .map(|(term, pages)| TermListing {
+                name: term,
+                permalink: context
+                    .config
+                    .make_permalink(&taxonomy.term_output_path(term)),
+                pages,
+            })
+            .collect::<Vec<_>>();
+
+        let mut ctx = tera::Context::new();
+
+        ctx.insert("config", &context.config);
+        ctx.insert("taxonomy", &taxonomy.name);
+        ctx.insert("terms", &terms);
+        ctx.insert("taxonomies", &site.taxonomies);
+        ctx.insert("current_url", &context.config.make_permalink(&output_path));
+        ctx.insert("last_updated", &Utc::now().to_string());
+
+        let contents = tera.render(&template_name, &ctx)?;
+        context.write_to_output(Path::new(&output_path), &contents)?;
     }
 
     Ok(())
@@ -367,14 +1609,18 @@ fn render_pages_for_site(
 
 fn process_taxonomies(
     context: &Context,
-    _tera: &Tera,
+    tera: &Tera,
+    highlighter: &Highlighter,
+    renderers: &Renderers,
     site: &mut Arc<RwLock<Site>>,
 ) -> anyhow::Result<()> {
     for taxonomy in &context.config.taxonomies {
         let terms = {
             let site = site.try_read().unwrap();
+            // drafts are only included for previewing, so shouldn't create term pages
             site.pages
                 .values()
+                .filter(|p| !p.draft)
                 .flat_map(|p| p.taxonomies.get(&taxonomy.name))
                 .flatten()
                 .cloned()
@@ -382,76 +1628,680 @@ fn process_taxonomies(
         };
 
         for term in terms {
-            let template_name = format!("{}/single.html", &taxonomy.name);
+            let template_name = taxonomy.term_template_name();
 
-            let output_path =
-                output_path(&Path::new(&taxonomy.name).join(&term), Some(&template_name));
+            let output_path = taxonomy.term_output_path(&term);
             let name = output_path.to_string();
             let permalink = context.config.make_permalink(&name);
 
+            let mut partial = PartialPage {
+                name: name.clone(),
+                title: term.to_string(),
+                description: String::new(),
+                date: None,
+                permalink,
+            };
+            let mut content = String::new();
+            let mut words = 0;
+
+            // an optional `content/{taxonomy}/{term}.md` fills in the term page
+            let description_path = context
+                .content_dir
+                .join(&taxonomy.name)
+                .join(format!("{}.md", term));
+            if description_path.is_file() {
+                println!(
+                    "compiling {}",
+                    context.relative(&description_path)?.display()
+                );
+
+                let contents = fs::read_to_string(&description_path)?;
+                let (frontmatter, body) = frontmatter::parse::<FrontMatter>(&contents)?;
+
+                if let Some(title) = frontmatter.title {
+                    partial.title = title;
+                }
+                partial.description = frontmatter.description.unwrap_or_default();
+                words = word_count(body, &context.config.markdown);
+
+                content = renderers.for_path(&description_path).render(
+                    body,
+                    &partial,
+                    tera,
+                    highlighter,
+                    &context.config,
+                )?;
+            }
+            let content = check_alt_text(context, &description_path, &content)?;
+            let (content, toc) = toc::heading_ids(&content, &context.config);
+
             let page = Page {
                 name: name.to_string(),
                 output_path: Path::new(&output_path).to_path_buf(),
                 template_name,
-                title: term.to_string(),
+                title: partial.title,
                 taxonomy: Some((taxonomy.name.to_string(), term.to_string())),
-                description: String::new(),
+                sort_by: None,
+                sort_reverse: false,
+                paginate_by: None,
+                section: None,
+                description_html: functions::markdown::render(
+                    &partial.description,
+                    &context.config.markdown,
+                ),
+                description: partial.description,
                 date: None,
-                permalink,
-                content: String::new(),
-                summary: None,
+                summary: split_summary(&content, &partial.permalink),
+                canonical: context.config.make_canonical(&partial.permalink),
+                permalink: partial.permalink,
+                content_text: strip_html(&content),
+                word_count: words,
+                reading_time: words.div_ceil(WORDS_PER_MINUTE),
+                toc,
+                ancestors: ancestors(context, &site.try_read().unwrap().sections, &name),
+                content_hash: Page::hash_content(&content),
+                content,
                 // key: String::new(),
                 taxonomies: HashMap::new(),
+                taxonomy_links: HashMap::new(),
+                draft: false,
+                previous: None,
+                next: None,
+                feed_full_content: context.config.feed_full_content,
+                extra: tera::Map::new().into(),
             };
 
             let mut site = site.try_write().unwrap();
+            if let Some(existing) = site.pages.get(&name) {
+                let existing = match &existing.taxonomy {
+                    Some((_, other)) => format!("term '{}'", other),
+                    None => format!("the content page '{}'", existing.title),
+                };
+                return Err(anyhow!(
+                    "term '{}' of taxonomy '{}' and {} would both be written to {}",
+                    term,
+                    taxonomy.name,
+                    existing,
+                    name
+                ));
+            }
             site.pages.insert(name, page);
         }
     }
 
+    let mut site = site.try_write().unwrap();
+
+    let mut taxonomies = HashMap::new();
+
+    for taxonomy in &context.config.taxonomies {
+        let mut terms: BTreeMap<String, Vec<Page>> = BTreeMap::new();
+
+        for page in site
+            .pages
+            .values()
+            .filter(|p| p.taxonomy.is_none() && !p.draft)
+        {
+            for term in page.taxonomies.get(&taxonomy.name).into_iter().flatten() {
+                terms.entry(term.clone()).or_default().push(page.clone());
+            }
+        }
+
+        for pages in terms.values_mut() {
+            pages.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.name.cmp(&b.name)));
+        }
+
+        taxonomies.insert(taxonomy.name.clone(), terms);
+    }
+
+    site.taxonomies = taxonomies;
+
     Ok(())
 }
 
+/// Split a URL path from any query and fragment that follow it
+fn split_url_suffix(path: &str) -> (&str, &str) {
+    let end = path.find(['?', '#']).unwrap_or(path.len());
+    path.split_at(end)
+}
+
+/// Make a path safe to use in a URL: each `/`-separated segment is transliterated to
+/// lowercase ASCII, with each run of characters other than letters, digits, `.` and `_`
+/// replaced by a single dash, and no dashes left at either end
 pub fn slugify(input: &str) -> String {
-    input.replace(' ', "-")
+    input
+        .split('/')
+        .map(|segment| {
+            deunicode::deunicode(segment)
+                .to_lowercase()
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+fn main() -> ExitCode {
+    let mut args = Args::parse();
+    args.local |= args.serve;
 
     println!("running with {args:?}");
 
-    let home = PathBuf::from_str(&args.path)?;
-    let output_dir = home.join(&args.output_dir);
+    let warnings = Warnings::default();
+    let data_files = DataFiles::default();
+
+    let start = Instant::now();
+    let result = build(&args, warnings.clone(), &data_files);
+    let elapsed = start.elapsed();
+
+    warnings.report();
+
+    let over_budget = args
+        .max_build_time
+        .filter(|max| elapsed.as_secs_f64() > *max);
+
+    let exit_code = match (&result, over_budget) {
+        (Err(e), _) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(1)
+        }
+        (Ok(()), Some(max)) => {
+            eprintln!(
+                "Error: build took {:.2}s, exceeding the budget of {}s",
+                elapsed.as_secs_f64(),
+                max
+            );
+            ExitCode::from(3)
+        }
+        (Ok(()), None) if args.fail_on_warning && !warnings.is_empty() => ExitCode::from(2),
+        (Ok(()), None) => ExitCode::SUCCESS,
+    };
+
+    if args.watch {
+        let builds = Arc::new(AtomicUsize::new(0));
+
+        if args.serve {
+            match output_dir(&args) {
+                Ok(dir) => {
+                    let builds = builds.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve::serve(&dir, SERVE_PORT, Some(builds)) {
+                            eprintln!("Error: {e:?}");
+                        }
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Error: {e:?}");
+                    return ExitCode::from(1);
+                }
+            }
+        }
+
+        // blocks until the process is interrupted
+        let home = Path::new(&args.path);
+        if let Err(e) = watch::watch(home, &args.content, &data_files, |paths| {
+            if rebuild(&args, &data_files, paths) {
+                builds.fetch_add(1, Ordering::SeqCst);
+            }
+        }) {
+            eprintln!("Error: {e:?}");
+            return ExitCode::from(1);
+        }
+    } else if args.serve && result.is_ok() {
+        // blocks until the process is interrupted
+        if let Err(e) = output_dir(&args).and_then(|dir| serve::serve(&dir, SERVE_PORT, None)) {
+            eprintln!("Error: {e:?}");
+            return ExitCode::from(1);
+        }
+    }
+
+    exit_code
+}
+
+/// Rebuild the whole site in watch mode, returning whether it succeeded. Errors are reported,
+/// but don't stop the watch, so they can be fixed in place.
+fn rebuild(args: &Args, data_files: &DataFiles, changed: &[&Path]) -> bool {
+    for path in changed {
+        println!("changed: {}", path.display());
+    }
+
+    let warnings = Warnings::default();
+
+    let start = Instant::now();
+    let result = build(args, warnings.clone(), data_files);
+    let elapsed = start.elapsed();
+
+    warnings.report();
+
+    match result {
+        Ok(()) => {
+            println!("rebuilt in {:.2}s", elapsed.as_secs_f64());
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            false
+        }
+    }
+}
 
-    let context = Context::new(home, output_dir, args.local, &args.base_url)?;
+/// The output directory, which is relative to the site unless given as an absolute path
+fn output_dir(args: &Args) -> anyhow::Result<PathBuf> {
+    let output_dir = PathBuf::from_str(&args.output_dir)?;
+    Ok(if output_dir.is_absolute() {
+        output_dir
+    } else {
+        PathBuf::from_str(&args.path)?.join(output_dir)
+    })
+}
+
+/// Build the site, adding any data files templates load to `data_files`
+fn build(args: &Args, warnings: Warnings, data_files: &DataFiles) -> anyhow::Result<()> {
+    let home = PathBuf::from_str(&args.path)?;
+    let output_dir = output_dir(args)?;
+
+    let mut context = Context::new(
+        home,
+        &args.content,
+        output_dir,
+        args.local,
+        &args.base_url,
+        args.no_highlight,
+        args.drafts,
+        warnings,
+    )?;
+
+    let only_page = args.page.as_ref().map(|page| {
+        let page = page.strip_prefix(&args.content).unwrap_or(page);
+        context.content_dir.join(page)
+    });
+
+    if let Some(page) = &only_page {
+        if !page.is_file() {
+            return Err(anyhow!("page {} not found", page.display()));
+        }
+    } else {
+        context.clean_output_dir()?;
 
-    context.clean_output_dir()?;
+        copy_static_files(&context)?;
+    }
 
-    copy_static_files(&context)?;
+    let highlighter = Highlighter::new(&context, args.cache_dir.as_deref())?;
 
-    let highlighter = Highlighter::new(&context)?;
+    if only_page.is_none() {
+        if let Some(stylesheet) = highlighter.stylesheet()? {
+            context.write_to_output(Path::new("syntax.css"), &stylesheet)?;
+        }
+    }
 
     let mut tera = setup_template_engine(&context)?;
 
-    tera.register_function("get_url", GetURL::new(context.config.base_url.clone()));
-    tera.register_function(
-        "get_taxonomy_url",
-        GetTaxonomyURL::new(context.config.base_url.clone(), &context.config.taxonomies),
-    );
-    tera.register_filter("markdown", Markdown {});
+    let asset_dirs = context
+        .static_dirs()
+        .into_iter()
+        .chain([context.output_dir.clone()])
+        .collect::<Vec<_>>();
+
+    let get_url = GetURL::new(context.config.clone(), asset_dirs.clone());
+    context.register_function(&mut tera, "get_url", get_url);
+    context.register_function(&mut tera, "asset_exists", AssetExists::new(asset_dirs));
+    let listed_taxonomies = context
+        .config
+        .taxonomies
+        .iter()
+        .filter(|t| {
+            let template_name = t.list_template_name();
+            tera.get_template_names().any(|name| name == template_name)
+        })
+        .map(|t| t.name.clone())
+        .collect();
+    let get_taxonomy_url = GetTaxonomyURL::new(context.config.clone(), listed_taxonomies);
+    context.register_function(&mut tera, "get_taxonomy_url", get_taxonomy_url);
+    let load_data = LoadData::new(context.home.clone(), data_files.clone());
+    context.register_function(&mut tera, "load_data", load_data);
+    tera.register_filter("markdown", Markdown::new(context.config.markdown));
+
+    let renderers = Renderers::new();
 
     let mut site = Arc::new(RwLock::new(process_templated_files(
         &context,
         &tera,
         &highlighter,
+        &renderers,
+        only_page.as_deref(),
+        args.shuffle_seed,
     )?));
 
-    tera.register_function("get_section", GetSection::new(site.clone()));
+    context.register_function(&mut tera, "get_page", GetPage::new(site.clone()));
+    let get_section = GetSection::new(site.clone(), context.config.clone());
+    context.register_function(&mut tera, "get_section", get_section);
+    let get_pages_by_date = GetPagesByDate::new(site.clone());
+    context.register_function(&mut tera, "get_pages_by_date", get_pages_by_date);
+    let get_taxonomy_siblings = GetTaxonomySiblings::new(site.clone());
+    context.register_function(&mut tera, "get_taxonomy_siblings", get_taxonomy_siblings);
 
-    process_taxonomies(&context, &tera, &mut site)?;
+    if only_page.is_none() {
+        process_taxonomies(&context, &tera, &highlighter, &renderers, &mut site)?;
+    }
 
-    render_pages_for_site(&context, &tera, site.clone())?;
+    render_pages_for_site(
+        &context,
+        &tera,
+        site.clone(),
+        only_page.is_none(),
+        args.shuffle_seed,
+    )?;
+
+    // a single page build only sees a fraction of the code blocks, so would prune the rest
+    if only_page.is_none() {
+        highlighter.save_cache()?;
+    }
 
     Ok(())
 }
+
+/// A config with just the required settings, followed by `extra` (more TOML)
+#[cfg(test)]
+pub fn test_config(extra: &str) -> Config {
+    test_config_at("https://example.com/", extra)
+}
+
+/// A config for a site at `base_url`, prepared as `Context::new` would
+#[cfg(test)]
+pub fn test_config_at(base_url: &str, extra: &str) -> Config {
+    let text = format!(
+        "title = \"Test\"\nbase_url = \"{}\"\ntaxonomies = []\n{}",
+        base_url, extra
+    );
+    let mut config: Config = toml::from_str(&text).unwrap();
+    config.normalize_base_urls();
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_with_or_without_trailing_slash() {
+        for base_url in ["https://example.com", "https://example.com/"] {
+            let config = test_config_at(base_url, "");
+            assert_eq!(
+                config.make_url("about/").as_str(),
+                "https://example.com/about/"
+            );
+            assert_eq!(
+                config.make_url("/about/").as_str(),
+                "https://example.com/about/"
+            );
+            assert_eq!(config.make_url("").as_str(), "https://example.com/");
+        }
+    }
+
+    #[test]
+    fn base_url_with_path_prefix() {
+        for base_url in ["https://example.com/docs", "https://example.com/docs/"] {
+            let config = test_config_at(base_url, "");
+            assert_eq!(
+                config.make_url("guide/").as_str(),
+                "https://example.com/docs/guide/"
+            );
+            assert_eq!(
+                config.make_url("/guide/").as_str(),
+                "https://example.com/docs/guide/"
+            );
+            assert_eq!(config.make_url("").as_str(), "https://example.com/docs/");
+        }
+    }
+
+    #[test]
+    fn base_path_with_spaced_terms() {
+        let config = test_config_at("https://example.com/blog", "");
+        assert_eq!(
+            config.make_url("tags/web dev/").as_str(),
+            "https://example.com/blog/tags/web-dev/"
+        );
+        assert_eq!(
+            config.make_url("/tags/Rust  &  WASM/").as_str(),
+            "https://example.com/blog/tags/rust-wasm/"
+        );
+        assert_eq!(
+            config.make_url("tags/web dev/#top").as_str(),
+            "https://example.com/blog/tags/web-dev/#top"
+        );
+    }
+
+    #[test]
+    fn taxonomy_slug_sets_term_urls() {
+        let config = test_config("");
+        let taxonomy: Taxonomy =
+            toml::from_str("name = \"categories\"\nslug = \"topics\"").unwrap();
+
+        let output_path = taxonomy.term_output_path("web dev");
+        assert_eq!(output_path, "topics/web-dev/index.html");
+        assert_eq!(
+            config.make_permalink(&output_path).as_str(),
+            "https://example.com/topics/web-dev/"
+        );
+        assert_eq!(taxonomy.term_template_name(), "categories/single.html");
+    }
+
+    #[test]
+    fn root_index_is_written_to_root() {
+        for path in ["index.md", "_index.md", ""] {
+            assert_eq!(
+                output_path(Path::new(path), Some("page.html"), false),
+                "index.html",
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn nested_index_is_written_to_its_directory() {
+        for path in ["blog/index.md", "blog/_index.md"] {
+            assert_eq!(
+                output_path(Path::new(path), Some("page.html"), false),
+                "blog/index.html"
+            );
+        }
+        assert_eq!(
+            output_path(Path::new("blog/_index.md"), Some("page.html"), true),
+            "blog/index.html"
+        );
+    }
+
+    #[test]
+    fn other_pages_get_a_directory() {
+        assert_eq!(
+            output_path(Path::new("blog/First Post.md"), Some("page.html"), false),
+            "blog/first-post/index.html"
+        );
+        assert_eq!(
+            output_path(Path::new("blog/post.md"), Some("page.html"), true),
+            "blog/post.html"
+        );
+        assert_eq!(
+            output_path(Path::new("feeds/posts.md"), Some("feed.xml"), false),
+            "feeds/posts.xml"
+        );
+        assert!(!is_index(Path::new("blog/indexes.md")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_file_creates_nothing_through_symlinks() {
+        let dir = std::env::temp_dir().join(format!("mini-ssg-output-file-{}", std::process::id()));
+        let (site, outside) = (dir.join("site"), dir.join("outside"));
+        fs::create_dir_all(site.join("content")).unwrap();
+        fs::create_dir_all(site.join("public")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(
+            site.join("config.toml"),
+            "title = \"Test\"\nbase_url = \"https://example.com/\"\ntaxonomies = []\n",
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(&outside, site.join("public").join("link")).unwrap();
+
+        let context = Context::new(
+            site.clone(),
+            Path::new("content"),
+            site.join("public"),
+            false,
+            &None,
+            true,
+            false,
+            Warnings::default(),
+        )
+        .unwrap();
+
+        assert!(context.output_file(Path::new("link/a/b.html")).is_err());
+        assert!(!outside.join("a").exists());
+        assert!(context.output_file(Path::new("a/b.html")).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A site in a fresh temporary directory with the given content files, along with its
+    /// directory for cleaning up
+    fn test_site(name: &str, files: &[(&str, &str)]) -> (PathBuf, Context) {
+        let dir = std::env::temp_dir().join(format!("mini-ssg-{}-{}", name, std::process::id()));
+        for (file, contents) in files {
+            let path = dir.join("content").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+        fs::write(
+            dir.join("config.toml"),
+            "title = \"Test\"\nbase_url = \"https://example.com/\"\ntaxonomies = []\n",
+        )
+        .unwrap();
+
+        let context = Context::new(
+            dir.clone(),
+            Path::new("content"),
+            dir.join("public"),
+            false,
+            &None,
+            true,
+            false,
+            Warnings::default(),
+        )
+        .unwrap();
+
+        (dir, context)
+    }
+
+    /// Process a single content file of a test site, without any templates
+    fn process_test_file(
+        name: &str,
+        file: &str,
+        contents: &str,
+    ) -> (PathBuf, Context, ProcessedFile) {
+        let (dir, context) = test_site(name, &[(file, contents)]);
+        let processed = process_templated_file(
+            &context,
+            &Tera::default(),
+            &Highlighter::for_tests(false),
+            &Renderers::new(),
+            &context.content_dir,
+            &context.content_dir.join(file),
+        )
+        .unwrap();
+
+        (dir, context, processed)
+    }
+
+    #[test]
+    fn pager_pages_collide_with_pages() {
+        let (dir, context) = test_site(
+            "pager",
+            &[
+                (
+                    "blog/_index.md",
+                    "+++\ntitle = \"Blog\"\npaginate_by = 1\n+++\n",
+                ),
+                ("blog/one.md", "+++\ntitle = \"One\"\n+++\n"),
+                ("blog/page/2.md", "+++\ntitle = \"Two\"\n+++\n"),
+            ],
+        );
+        let mut tera = Tera::default();
+        tera.add_raw_template(SECTION_TEMPLATE, "").unwrap();
+
+        let result = process_templated_files(
+            &context,
+            &tera,
+            &Highlighter::for_tests(false),
+            &Renderers::new(),
+            None,
+            None,
+        );
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "content/blog/page/2.md and page 2 of content/blog/_index.md would both be written \
+            to blog/page/2/index.html"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn book_chapters_are_undated() {
+        let (dir, _, processed) = process_test_file(
+            "chapters",
+            "book.md",
+            "+++\ntitle = \"Book\"\ndate = 2024-01-02\nsplit_on = \"h1\"\n+++\nIntro\n\n# One\n\nText\n",
+        );
+
+        let dates = processed
+            .pages
+            .iter()
+            .map(|p| (p.title.as_str(), p.date.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(dates, [("Book", true), ("One", false)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn section_content_without_a_template() {
+        let (dir, context, _) =
+            process_test_file("section", "blog/_index.md", "+++\ntitle = \"Blog\"\n+++\n");
+        assert!(context.warnings.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let (dir, context, processed) = process_test_file(
+            "section-content",
+            "blog/_index.md",
+            "+++\ntitle = \"Blog\"\n+++\nAll about the blog.\n",
+        );
+        assert!(processed.pages.is_empty());
+        assert!(!context.warnings.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn slugify_transliterates_accents() {
+        assert_eq!(slugify("Café Résumé"), "cafe-resume");
+    }
+
+    #[test]
+    fn slugify_replaces_punctuation() {
+        assert_eq!(slugify("Rust & WebAssembly"), "rust-webassembly");
+    }
+
+    #[test]
+    fn slugify_collapses_repeated_spaces() {
+        assert_eq!(slugify("  web   dev  "), "web-dev");
+    }
+
+    #[test]
+    fn slugify_preserves_slashes() {
+        assert_eq!(
+            slugify("Blog Posts/First Post.md"),
+            "blog-posts/first-post.md"
+        );
+    }
+}
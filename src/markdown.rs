@@ -1,25 +1,33 @@
-use anyhow::anyhow;
-use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Tag};
-use std::{ops::Range, str::FromStr};
+use anyhow::{anyhow, Context};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, HeadingLevel, Tag};
+use std::{collections::BTreeSet, ops::Range, str::FromStr};
 use tera::Tera;
 use url::Url;
 
 use combine::{
-    between,
+    attempt, between, choice, optional,
     parser::{
-        char::{spaces, string as Str},
-        range::take_while,
-        repeat::SepBy,
+        char::{digit, spaces, string as Str},
+        range::{recognize, take_while},
+        repeat::{skip_many1, SepBy},
     },
-    sep_by, EasyParser, Parser, Stream,
+    sep_by, token, EasyParser, Parser, Stream,
 };
 
-use crate::{highlighter::Highlighter, page::PartialPage};
+use crate::{
+    highlighter::{CodeBlockOptions, Highlighter},
+    page::PartialPage,
+    renderer::ContentRenderer,
+    toc::TOC,
+    Config, MarkdownConfig,
+};
 
+/// A shortcode argument, either named (`name="value"`) or positional. Values are quoted
+/// strings, numbers, or `true`/`false`.
 #[derive(Clone, Debug)]
 pub struct Argument {
-    name: String,
-    value: String,
+    name: Option<String>,
+    value: tera::Value,
 }
 
 #[derive(Clone, Debug)]
@@ -35,24 +43,44 @@ where
     Str(l).map(|s| s.to_string()).skip(spaces())
 }
 
-fn parse_shortcode(input: &str) -> anyhow::Result<ShortCode> {
+fn parse_shortcode(input: &str, config: &Config) -> anyhow::Result<ShortCode> {
+    let input = input
+        .strip_prefix(config.shortcode_open.as_str())
+        .and_then(|i| i.strip_suffix(config.shortcode_close.as_str()))
+        .ok_or_else(|| anyhow!("shortcode {} is missing its delimiters", input))?;
+
     let ident = || take_while(|c: char| c.is_alphanumeric() || c == '_').skip(spaces());
-    let literal_str = between(lit("\""), lit("\""), take_while(|c: char| c != '\"')).skip(spaces());
-    let arg = (ident(), lit("="), literal_str).map(|t: (&str, String, &str)| Argument {
-        name: t.0.to_string(),
-        value: t.2.to_string(),
+    let value = || {
+        let literal_str = between(lit("\""), lit("\""), take_while(|c: char| c != '\"'))
+            .map(|s: &str| tera::Value::from(s));
+        let literal_bool = choice((
+            attempt(Str("true")).map(|_| tera::Value::from(true)),
+            attempt(Str("false")).map(|_| tera::Value::from(false)),
+        ));
+        let literal_number = recognize((
+            optional(token('-')),
+            skip_many1(digit()),
+            optional((token('.'), skip_many1(digit()))),
+        ))
+        .map(|s: &str| match s.parse::<i64>() {
+            Ok(n) => tera::Value::from(n),
+            Err(_) => tera::Value::from(s.parse::<f64>().unwrap()),
+        });
+        choice((literal_str, literal_bool, literal_number)).skip(spaces())
+    };
+    let named = (ident(), lit("="), value()).map(|t: (&str, String, tera::Value)| Argument {
+        name: Some(t.0.to_string()),
+        value: t.2,
     });
+    let positional = value().map(|value| Argument { name: None, value });
+    let arg = choice((attempt(named), positional));
     let arg_list: SepBy<Vec<_>, _, _> = sep_by(arg, lit(","));
     let args = between(lit("("), lit(")"), arg_list);
 
-    let mut function = between(
-        lit("{{"),
-        lit("}}"),
-        (ident(), args).map(|t: (&str, _)| ShortCode {
-            name: t.0.to_string(),
-            arguments: t.1,
-        }),
-    );
+    let mut function = spaces().with((ident(), args).map(|t: (&str, _)| ShortCode {
+        name: t.0.to_string(),
+        arguments: t.1,
+    }));
 
     let result = function
         .easy_parse(input)
@@ -62,45 +90,189 @@ fn parse_shortcode(input: &str) -> anyhow::Result<ShortCode> {
     Ok(result.0)
 }
 
-pub fn render_shortcode(input: &str, page: &PartialPage, tera: &Tera) -> anyhow::Result<String> {
-    let shortcode = parse_shortcode(input)?;
+/// A template name without its extension, e.g. `figure` for `figure.html`
+fn template_stem(template: &str) -> &str {
+    template.rsplit_once('.').map_or(template, |(stem, _)| stem)
+}
+
+/// Shortcode templates live under `shortcodes/`. Their output is inserted verbatim as HTML,
+/// unless the template has a `.md` extension, in which case the output is rendered as markdown.
+/// Along with the output, returns whether it is block-level (see `block_shortcodes`).
+pub fn render_shortcode(
+    input: &str,
+    page: &PartialPage,
+    tera: &Tera,
+    highlighter: &Highlighter,
+    config: &Config,
+) -> anyhow::Result<(String, bool)> {
+    let shortcode = parse_shortcode(input, config)?;
 
     for template in tera.get_template_names() {
         if let Some(name) = template.strip_prefix("shortcodes/") {
-            let mut short_name = name.to_string();
-            if let Some(i) = short_name.rfind('.') {
-                short_name = short_name[0..i].to_string();
-            }
-
-            if short_name == shortcode.name {
+            if template_stem(name) == shortcode.name {
                 let mut ctx = tera::Context::new();
 
+                // Tera names can't start with a digit, so positional arguments are `args[0]`
+                // and so on. Without any, a named `args` argument is left alone.
+                let mut positional = vec![];
                 for arg in &shortcode.arguments {
-                    ctx.insert(&arg.name, &arg.value);
+                    match &arg.name {
+                        Some(name) => ctx.insert(name, &arg.value),
+                        None => positional.push(&arg.value),
+                    }
+                }
+                if !positional.is_empty() {
+                    ctx.insert("args", &positional);
                 }
 
                 ctx.insert("page", page);
 
-                return Ok(tera.render(template, &ctx)?);
+                let output = tera.render(template, &ctx).with_context(|| {
+                    let mut provided = shortcode
+                        .arguments
+                        .iter()
+                        .filter_map(|a| a.name.clone())
+                        .collect::<Vec<_>>();
+                    provided.extend((0..positional.len()).map(|i| format!("args[{}]", i)));
+                    format!(
+                        "failed to render shortcode '{}' on page '{}' (arguments provided: [{}])",
+                        shortcode.name,
+                        page.name,
+                        provided.join(", ")
+                    )
+                })?;
+
+                if template.ends_with(".md") {
+                    return Ok((render_markdown(&output, page, highlighter, config)?, true));
+                }
+
+                let block = config.block_shortcodes.contains(&shortcode.name);
+                return Ok((output, block));
             }
         }
     }
 
-    Err(anyhow!("unknown shortcode '{}'", shortcode.name))
+    let available = tera
+        .get_template_names()
+        .filter_map(|t| t.strip_prefix("shortcodes/"))
+        .map(template_stem)
+        .collect::<BTreeSet<_>>();
+    let mut message = format!(
+        "unknown shortcode '{}' (available shortcodes: [{}])",
+        shortcode.name,
+        available.into_iter().collect::<Vec<_>>().join(", ")
+    );
+
+    // a likely cause is a template that was put in the wrong place
+    if let Some(misplaced) = tera
+        .get_template_names()
+        .find(|t| !t.starts_with("shortcodes/") && template_stem(t) == shortcode.name)
+    {
+        message.push_str(&format!(
+            ", but there is a template '{}': shortcodes need to be under templates/shortcodes/",
+            misplaced
+        ));
+    }
+
+    Err(anyhow!(message))
+}
+
+/// Turn admonition containers into `<div class="admonition {type}">` HTML blocks, i.e.
+///
+/// ```text
+/// :::note
+/// Some *markdown*
+/// :::
+/// ```
+///
+/// The markers are replaced in the markdown rather than rendering each container by itself,
+/// so that the whole page is still parsed as one document, and reference links and footnotes
+/// resolve across containers. Only containers whose type is listed in `admonition_types` are
+/// turned into admonitions. Containers may be nested, markers inside fenced code blocks are
+/// ignored, and an unclosed container is left as plain text.
+fn expand_admonitions<'a>(input: &'a str, config: &Config) -> String {
+    let marker = config.admonition_marker.as_str();
+    let container_type = |line: &'a str| line.strip_prefix(marker)?.split_whitespace().next();
+
+    // the byte range of each marker line to replace, and what to replace it with
+    let mut replacements = vec![];
+    // the type (if it's an admonition) and opening line of each open container
+    let mut open: Vec<(Option<&'a str>, Range<usize>)> = vec![];
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_end();
+
+        match fence {
+            Some(f) if trimmed.trim_start().starts_with(f) => {
+                fence = None;
+                continue;
+            }
+            Some(_) => continue,
+            None if trimmed.trim_start().starts_with("```") => {
+                fence = Some("```");
+                continue;
+            }
+            None if trimmed.trim_start().starts_with("~~~") => {
+                fence = Some("~~~");
+                continue;
+            }
+            None => {}
+        }
+
+        if let Some(kind) = container_type(trimmed) {
+            // containers of other types are only tracked so that their closing marker
+            // isn't mistaken for the end of an admonition
+            let admonition = config.admonition_types.iter().any(|t| t == kind);
+            open.push((admonition.then_some(kind), start..offset));
+        } else if trimmed == marker {
+            if let Some((Some(kind), opening)) = open.pop() {
+                // blank lines end the HTML blocks, so the body is parsed as markdown
+                let div = format!("<div class=\"admonition {}\">\n\n", kind);
+                replacements.push((opening, div));
+                replacements.push((start..offset, "\n</div>\n\n".to_string()));
+            }
+        }
+    }
+
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut output = String::with_capacity(input.len());
+    let mut last = 0;
+    for (range, replacement) in replacements {
+        output.push_str(&input[last..range.start]);
+        output.push_str(&replacement);
+        last = range.end;
+    }
+    output.push_str(&input[last..]);
+
+    output
+}
+
+/// A markdown parser with the extensions turned on in the site's `[markdown]` config, shared
+/// by page content and the `markdown` filter so they render alike
+pub fn parser<'a>(input: &'a str, config: &MarkdownConfig) -> pulldown_cmark::Parser<'a, 'a> {
+    pulldown_cmark::Parser::new_ext(input, config.options())
 }
 
 pub fn render_markdown(
     input: &str,
     page: &PartialPage,
     highlighter: &Highlighter,
+    config: &Config,
 ) -> anyhow::Result<String> {
+    let input = expand_admonitions(input, config);
+
     let mut events = vec![];
 
     let mut in_code_block = false;
-    let mut lang = String::new();
+    let mut options = CodeBlockOptions::default();
     let mut code = String::new();
 
-    for event in pulldown_cmark::Parser::new(input) {
+    for event in parser(&input, &config.markdown) {
         match event {
             Event::Start(Tag::Image(link_type, mut dest_url, title)) => {
                 // transform any relative URLs to absolute
@@ -114,17 +286,31 @@ pub fn render_markdown(
             }
             Event::Start(Tag::CodeBlock(kind)) => {
                 in_code_block = true;
-                lang = if let CodeBlockKind::Fenced(name) = kind {
-                    name.to_string()
+                options = if let CodeBlockKind::Fenced(info) = kind {
+                    CodeBlockOptions::parse(&info)?
                 } else {
-                    "".to_string()
+                    CodeBlockOptions::default()
                 };
             }
+            // the page's title is the template's to render as its only `<h1>`
+            Event::Start(Tag::Heading(level, id, classes)) if config.demote_headings => {
+                events.push(Event::Start(Tag::Heading(demote(level), id, classes)));
+            }
+            Event::End(Tag::Heading(level, id, classes)) if config.demote_headings => {
+                events.push(Event::End(Tag::Heading(demote(level), id, classes)));
+            }
             Event::Text(t) if in_code_block => {
                 code.push_str(&t);
             }
             Event::End(Tag::CodeBlock(_)) if in_code_block => {
-                let result = highlighter.highlight(&lang, &code)?;
+                let mut result = highlighter.highlight(&options, &code)?;
+                if let Some(name) = &options.name {
+                    result = format!(
+                        "<figure class=\"code-block\"><figcaption>{}</figcaption>{}</figure>\n",
+                        tera::escape_html(name),
+                        result
+                    );
+                }
 
                 events.push(Event::Html(CowStr::from(result)));
 
@@ -141,54 +327,663 @@ pub fn render_markdown(
     Ok(contents)
 }
 
-enum ContentRange {
-    Markdown(Range<usize>),
-    ShortCode(Range<usize>),
+/// One heading level lower, bottoming out at `<h6>`
+fn demote(level: HeadingLevel) -> HeadingLevel {
+    HeadingLevel::try_from(level as usize + 1).unwrap_or(HeadingLevel::H6)
+}
+
+pub struct MarkdownRenderer;
+
+impl ContentRenderer for MarkdownRenderer {
+    fn render(
+        &self,
+        input: &str,
+        page: &PartialPage,
+        tera: &Tera,
+        highlighter: &Highlighter,
+        config: &Config,
+    ) -> anyhow::Result<String> {
+        render_content(input, page, tera, highlighter, config)
+    }
 }
 
+/// Stands in for a shortcode's output while the markdown around it is rendered. Private use
+/// characters and digits pass through markdown untouched.
+fn shortcode_placeholder(index: usize) -> String {
+    format!("\u{E000}{}\u{E001}", index)
+}
+
+/// Byte ranges of the code blocks and code spans in markdown
+fn code_ranges(input: &str, config: &MarkdownConfig) -> Vec<Range<usize>> {
+    parser(input, config)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Render page content: shortcodes are expanded first, and the markdown is then rendered as a
+/// whole with a placeholder in place of each, so a shortcode inline in a paragraph stays in
+/// that paragraph. Block-level shortcodes get blank lines around their placeholder, so they
+/// always form a paragraph of their own, and a paragraph holding nothing but a shortcode is
+/// replaced by its output outright. Shortcodes in code blocks and code spans are left as
+/// they're written.
 pub fn render_content(
     input: &str,
     page: &PartialPage,
     tera: &Tera,
     highlighter: &Highlighter,
+    config: &Config,
 ) -> anyhow::Result<String> {
-    let mut input = input.to_string();
+    let (open, close) = (&config.shortcode_open, &config.shortcode_close);
 
-    let mut ranges = vec![];
+    let mut markdown = String::with_capacity(input.len());
+    let mut outputs = vec![];
+    let code = code_ranges(input, &config.markdown);
 
+    // `find` returns the byte offset at which a whole delimiter starts, so every slice
+    // below starts and ends on a char boundary, even right next to multi-byte characters
     let mut last = 0;
-    while let Some(start) = input[last..].find("{{") {
-        if start > 0 {
-            ranges.push(ContentRange::Markdown(last..last + start));
+    while let Some(start) = input[last..].find(open.as_str()) {
+        let start = last + start;
+
+        if let Some(code) = code.iter().find(|code| code.contains(&start)) {
+            markdown.push_str(&input[last..code.end]);
+            last = code.end;
+            continue;
         }
 
-        if let Some(end) = input[last + start..].find("}}") {
-            ranges.push(ContentRange::ShortCode(
-                last + start..last + start + end + 2,
-            ));
-            last = last + start + end + 2;
+        // with `[[`/`]]` delimiters, the table of contents marker looks like a shortcode
+        if input[start..].starts_with(TOC) {
+            markdown.push_str(&input[last..start + TOC.len()]);
+            last = start + TOC.len();
+            continue;
+        }
+
+        markdown.push_str(&input[last..start]);
+
+        let end = match input[start + open.len()..].find(close.as_str()) {
+            Some(end) => start + open.len() + end + close.len(),
+            None => return Err(anyhow!("unterminated shortcode")),
+        };
+        debug_assert!(input.is_char_boundary(end));
+
+        let (output, block) =
+            render_shortcode(&input[start..end], page, tera, highlighter, config)?;
+        let placeholder = shortcode_placeholder(outputs.len());
+        if block {
+            markdown.push_str(&format!("\n\n{}\n\n", placeholder));
         } else {
-            return Err(anyhow!("unterminated shortcode"));
+            markdown.push_str(&placeholder);
         }
+        outputs.push((placeholder, output));
+
+        last = end;
     }
+    markdown.push_str(&input[last..]);
 
-    if last < input.len() {
-        ranges.push(ContentRange::Markdown(last..input.len()))
+    let mut content = render_markdown(&markdown, page, highlighter, config)?;
+
+    for (placeholder, output) in outputs {
+        let paragraph = format!("<p>{}</p>\n", placeholder);
+        if content.contains(&paragraph) {
+            content = content.replacen(&paragraph, &output, 1);
+        } else if content.contains(&placeholder) {
+            content = content.replacen(&placeholder, &output, 1);
+        } else {
+            // e.g. in a link destination, where it's percent-encoded
+            return Err(anyhow!(
+                "couldn't place the output of a shortcode on page '{}'",
+                page.name
+            ));
+        }
     }
 
-    ranges.reverse();
+    Ok(content)
+}
 
-    for range in ranges {
-        match range {
-            ContentRange::Markdown(r) => input.replace_range(
-                r.clone(),
-                &render_markdown(&input[r.clone()], page, highlighter)?,
-            ),
-            ContentRange::ShortCode(r) => {
-                input.replace_range(r.clone(), &render_shortcode(&input[r.clone()], page, tera)?)
+/// Elements that never have a closing tag
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Extract the summary from rendered page content, i.e. everything before a `<!-- more -->`
+/// marker. If the marker sits inside an element (for example, inline in a paragraph),
+/// any elements still open at that point are closed so the summary remains valid HTML.
+///
+/// Summaries are usually shown on other pages, so links to fragments of this page
+/// (`href="#section"`) are made absolute, and `id`s are dropped, since they would clash with
+/// those of the other summaries (and the page itself) listed alongside.
+pub fn split_summary(content: &str, permalink: &Url) -> Option<String> {
+    let mut offset = 0;
+
+    let marker = loop {
+        let start = offset + content[offset..].find("<!--")?;
+        let end = start + 4 + content[start + 4..].find("-->")?;
+
+        if content[start + 4..end].trim().eq_ignore_ascii_case("more") {
+            break start;
+        }
+
+        offset = end + 3;
+    };
+
+    let summary = &content[..marker];
+
+    let mut open_elements: Vec<&str> = vec![];
+    let mut rest = summary;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|i| &comment[i + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            if let Some(i) = open_elements
+                .iter()
+                .rposition(|e| e.eq_ignore_ascii_case(name))
+            {
+                open_elements.truncate(i);
+            }
+        } else if !tag.ends_with('/') && !tag.starts_with('!') {
+            let name = tag
+                .split(|c: char| c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            if !VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+                open_elements.push(name);
+            }
+        }
+    }
+
+    let mut summary = summary.to_string();
+    for name in open_elements.iter().rev() {
+        summary.push_str(&format!("</{}>", name));
+    }
+
+    let mut page_url = permalink.clone();
+    page_url.set_fragment(None);
+    for quote in ['"', '\''] {
+        summary = summary.replace(
+            &format!("href={}#", quote),
+            &format!("href={}{}#", quote, page_url),
+        );
+    }
+
+    Some(strip_ids(&summary))
+}
+
+/// Remove the `id` attribute from every tag
+fn strip_ids(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+
+        let mut tag = &rest[start..end];
+        while let Some(id) = tag.find(" id=\"") {
+            let value_end = match tag[id + 5..].find('"') {
+                Some(value_end) => id + 5 + value_end + 1,
+                None => break,
+            };
+            output.push_str(&tag[..id]);
+            tag = &tag[value_end..];
+        }
+        output.push_str(tag);
+
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Elements that don't break up the text around them, so stripping them shouldn't add a space
+const INLINE_ELEMENTS: [&str; 15] = [
+    "a", "abbr", "b", "code", "del", "em", "i", "kbd", "mark", "s", "small", "span", "strong",
+    "sub", "sup",
+];
+
+/// Find the `<img>` tags in rendered content that have no alt text, either no `alt` at all or
+/// an empty one. Returns the `src` of each, along with the content where they're given
+/// `fallback` as their alt text, if there is one.
+pub fn missing_alt_text(content: &str, fallback: Option<&str>) -> (String, Vec<String>) {
+    let mut output = String::with_capacity(content.len());
+    let mut missing = vec![];
+    let mut rest = content;
+
+    while let Some(start) = rest.find("<img") {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let tag = &rest[start..end];
+        output.push_str(&rest[..start]);
+        rest = &rest[end..];
+
+        // not an image, but some other tag starting with `img`
+        if !tag[4..].starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/') {
+            output.push_str(tag);
+            continue;
+        }
+
+        let attributes = attributes(tag);
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(_, n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(range, _, value)| (range.clone(), *value))
+        };
+
+        let alt = attribute("alt");
+        if alt.as_ref().is_some_and(|(_, alt)| !alt.trim().is_empty()) {
+            output.push_str(tag);
+            continue;
+        }
+
+        missing.push(
+            attribute("src")
+                .map(|(_, src)| src)
+                .unwrap_or_default()
+                .to_string(),
+        );
+
+        match (fallback, alt) {
+            (Some(fallback), Some((range, _))) => {
+                output.push_str(&tag[..range.start]);
+                output.push_str(&format!(" alt=\"{}\"", tera::escape_html(fallback)));
+                output.push_str(&tag[range.end..]);
+            }
+            (Some(fallback), None) => {
+                output.push_str("<img alt=\"");
+                output.push_str(&tera::escape_html(fallback));
+                output.push('"');
+                output.push_str(&tag[4..]);
             }
+            (None, _) => output.push_str(tag),
         }
     }
 
-    Ok(input)
+    output.push_str(rest);
+
+    (output, missing)
+}
+
+/// The attributes of an HTML start tag like `<img src="a.png" alt='A'>`: the range each covers
+/// in the tag (including the whitespace before it), its name, and its value. Values may be
+/// double-quoted, single-quoted, unquoted, or left out altogether.
+fn attributes(tag: &str) -> Vec<(Range<usize>, &str, &str)> {
+    let bytes = tag.as_bytes();
+    let is_space = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_whitespace);
+    let mut attributes = vec![];
+
+    // skip the `<` and tag name
+    let mut i = 1 + tag[1..]
+        .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+        .unwrap_or(tag.len() - 1);
+
+    loop {
+        let start = i;
+        while is_space(i) {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' || bytes[i] == b'/' {
+            break;
+        }
+
+        let name_start = i;
+        while i < bytes.len() && !is_space(i) && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let name = &tag[name_start..i];
+
+        let mut j = i;
+        while is_space(j) {
+            j += 1;
+        }
+
+        let mut value = "";
+        if bytes.get(j) == Some(&b'=') {
+            j += 1;
+            while is_space(j) {
+                j += 1;
+            }
+
+            match bytes.get(j) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let value_start = j + 1;
+                    let value_end = tag[value_start..]
+                        .find(quote as char)
+                        .map_or(tag.len(), |end| value_start + end);
+                    value = &tag[value_start..value_end];
+                    i = (value_end + 1).min(tag.len());
+                }
+                _ => {
+                    let value_start = j;
+                    while j < bytes.len() && !is_space(j) && bytes[j] != b'>' {
+                        j += 1;
+                    }
+                    value = &tag[value_start..j];
+                    i = j;
+                }
+            }
+        }
+
+        attributes.push((start..i, name, value));
+    }
+
+    attributes
+}
+
+/// Reduce rendered page content to plain text, for search indexes, meta descriptions
+/// and the like. Tags, comments, scripts and styles are dropped, the common entities
+/// are decoded, and runs of whitespace are collapsed to a single space.
+pub fn strip_html(content: &str) -> String {
+    let mut text = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|i| &comment[i + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        // the contents of scripts and styles aren't text
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            rest = rest
+                .to_ascii_lowercase()
+                .find(&close)
+                .and_then(|i| rest[i..].find('>').map(|j| &rest[i + j + 1..]))
+                .unwrap_or("");
+        }
+
+        if !INLINE_ELEMENTS.contains(&name.as_str()) {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Words a reader gets through in a minute, for `Page::reading_time`
+pub const WORDS_PER_MINUTE: usize = 200;
+
+/// The number of words in a markdown source, not counting code blocks (which would
+/// inflate it) or markup. Shortcodes aren't expanded, so the count is the same whatever
+/// they render.
+pub fn word_count(markdown: &str, config: &MarkdownConfig) -> usize {
+    let mut in_code_block = false;
+    let mut count = 0;
+
+    for event in parser(markdown, config) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(text) | Event::Code(text) if !in_code_block => {
+                count += text.split_whitespace().count();
+            }
+            _ => {}
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+
+    fn test_page() -> PartialPage {
+        PartialPage {
+            name: "test/index.html".to_string(),
+            title: "Test".to_string(),
+            description: String::new(),
+            date: None,
+            permalink: Url::parse("https://example.com/test/").unwrap(),
+        }
+    }
+
+    fn test_tera() -> Tera {
+        let mut tera = Tera::default();
+        tera.add_raw_templates([
+            (
+                "shortcodes/figure.html",
+                "<figure><img src=\"{{ src }}\"></figure>",
+            ),
+            ("shortcodes/icon.html", "<i class=\"{{ name }}\"></i>"),
+            (
+                "shortcodes/list.html",
+                "<b>{% if args is iterable %}{{ args | join(sep=\",\") }}{% else %}{{ args }}{% endif %}</b>",
+            ),
+        ])
+        .unwrap();
+        tera
+    }
+
+    fn render(input: &str, config: &Config, highlight: bool) -> String {
+        let highlighter = Highlighter::for_tests(highlight);
+        render_content(input, &test_page(), &test_tera(), &highlighter, config).unwrap()
+    }
+
+    fn render_with(input: &str, config: &MarkdownConfig) -> String {
+        let mut output = String::new();
+        html::push_html(&mut output, parser(input, config));
+        output
+    }
+
+    #[test]
+    fn pipe_tables_are_rendered() {
+        assert_eq!(
+            render_with(
+                "| a | b |\n|---|---|\n| 1 | 2 |\n",
+                &MarkdownConfig::default()
+            ),
+            "<table><thead><tr><th>a</th><th>b</th></tr></thead><tbody>\n\
+            <tr><td>1</td><td>2</td></tr>\n</tbody></table>\n"
+        );
+    }
+
+    #[test]
+    fn strikethrough_is_rendered() {
+        assert_eq!(
+            render_with("~~strike~~", &MarkdownConfig::default()),
+            "<p><del>strike</del></p>\n"
+        );
+    }
+
+    #[test]
+    fn smart_punctuation_leaves_code_alone() {
+        let config = MarkdownConfig {
+            smart_punctuation: true,
+            ..MarkdownConfig::default()
+        };
+        assert_eq!(
+            render_with("\"a\" -- `b--\"c\"`", &config),
+            "<p>“a” – <code>b--&quot;c&quot;</code></p>\n"
+        );
+    }
+
+    #[test]
+    fn demote_headings_shifts_every_heading() {
+        let config = test_config("demote_headings = true");
+        assert_eq!(
+            render("# Title\n\n## Section\n\n###### Deepest\n", &config, false),
+            "<h2>Title</h2>\n<h3>Section</h3>\n<h6>Deepest</h6>\n"
+        );
+    }
+
+    #[test]
+    fn toc_marker_with_square_bracket_delimiters() {
+        let config = test_config("shortcode_open = \"[[\"\nshortcode_close = \"]]\"");
+        let output = render(
+            "[[toc]]\n\n# One\n\n[[ icon(name=\"star\") ]]\n",
+            &config,
+            false,
+        );
+        let (output, _) = crate::toc::heading_ids(&output, &config);
+
+        assert!(output.contains("<ul class=\"toc\"><li><a href=\"#one\">One</a></li></ul>"));
+        assert!(output.contains("<i class=\"star\"></i>"));
+        assert!(!output.contains("[[toc]]"));
+    }
+
+    #[test]
+    fn admonitions() {
+        let config = test_config("");
+        let output = render(
+            "Intro\n:::note\nSome *text*\n\n:::tip\nNested\n:::\n:::\n\n```\n:::note\n```\n\n:::other\nPlain\n:::\n",
+            &config,
+            false,
+        );
+        assert_eq!(
+            output,
+            "<p>Intro</p>\n<div class=\"admonition note\">\n<p>Some <em>text</em></p>\n\
+            <div class=\"admonition tip\">\n<p>Nested</p>\n</div>\n</div>\n\
+            <pre><code>:::note\n</code></pre>\n<p>:::other\nPlain\n:::</p>\n"
+        );
+    }
+
+    #[test]
+    fn links_resolve_across_admonitions() {
+        let config = test_config("");
+        let output = render(
+            ":::note\nSee [the docs][docs] and a note[^1].\n:::\n\n[docs]: https://example.com/docs\n[^1]: The note.\n",
+            &config,
+            false,
+        );
+        assert!(output.contains("<a href=\"https://example.com/docs\">the docs</a>"));
+        assert!(output.contains("<sup class=\"footnote-reference\"><a href=\"#1\">1</a></sup>"));
+    }
+
+    #[test]
+    fn named_args_argument() {
+        let config = test_config("");
+        assert_eq!(
+            render(
+                "{{ list(\"a\", \"b\") }} {{ list(args=\"c\") }}",
+                &config,
+                false
+            ),
+            "<p><b>a,b</b> <b>c</b></p>\n"
+        );
+    }
+
+    #[test]
+    fn alt_text_in_any_quotes() {
+        let content = "<img src=\"a.png\" alt=\"A\"><img alt='B' src='b.png'>\
+            <img src=c.png alt=C><img title=\"alt=x\" src=\"d.png\">\
+            <img src=\"e.png\" alt=''><img src=\"f.png\" alt>";
+
+        let (output, missing) = missing_alt_text(content, Some("Image"));
+        assert_eq!(missing, ["d.png", "e.png", "f.png"]);
+        assert_eq!(
+            output,
+            "<img src=\"a.png\" alt=\"A\"><img alt='B' src='b.png'>\
+            <img src=c.png alt=C><img alt=\"Image\" title=\"alt=x\" src=\"d.png\">\
+            <img src=\"e.png\" alt=\"Image\"><img src=\"f.png\" alt=\"Image\">"
+        );
+    }
+
+    #[test]
+    fn summary_drops_ids() {
+        let permalink = Url::parse("https://example.com/post/").unwrap();
+        let content = "<h2 id=\"introduction\"><a class=\"anchor\" href=\"#introduction\"></a>\
+            Introduction</h2>\n<!-- more -->\n<p>Rest</p>";
+
+        assert_eq!(
+            split_summary(content, &permalink).unwrap(),
+            "<h2><a class=\"anchor\" href=\"https://example.com/post/#introduction\"></a>\
+            Introduction</h2>\n"
+        );
+    }
+
+    #[test]
+    fn block_shortcode_is_set_apart_from_its_paragraph() {
+        let config = test_config("block_shortcodes = [\"figure\"]");
+        assert_eq!(
+            render("Before {{ figure(src=\"a.png\") }} after.", &config, false),
+            "<p>Before </p>\n<figure><img src=\"a.png\"></figure><p>after.</p>\n"
+        );
+    }
+
+    #[test]
+    fn inline_shortcode_stays_in_its_paragraph() {
+        let config = test_config("");
+        assert_eq!(
+            render("Before {{ icon(name=\"star\") }} after.", &config, false),
+            "<p>Before <i class=\"star\"></i> after.</p>\n"
+        );
+    }
+
+    #[test]
+    fn multi_byte_characters_next_to_shortcodes() {
+        let config = test_config("");
+        assert_eq!(
+            render(
+                "é{{ icon(name=\"ü\") }}日本 👍{{ icon(name=\"x\") }}",
+                &config,
+                false
+            ),
+            "<p>é<i class=\"ü\"></i>日本 👍<i class=\"x\"></i></p>\n"
+        );
+    }
+
+    #[test]
+    fn shortcodes_in_code_are_left_as_written() {
+        let config = test_config("");
+        let input = "`{{ icon(name=\"a\") }}`\n\n```\n{{ icon(name=\"b\") }}\n```\n";
+
+        for highlight in [false, true] {
+            let output = render(input, &config, highlight);
+            assert!(output.contains("<code>{{ icon(name=&quot;a&quot;) }}</code>"));
+            assert!(!output.contains("<i class"));
+        }
+    }
 }
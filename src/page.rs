@@ -1,11 +1,18 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{cmp::Ordering, collections::HashMap, path::PathBuf};
+
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
 
 use serde::Serialize;
 use url::Url;
 
+use crate::{section::SectionLink, toc::TocEntry};
+
 /// Page variables that are available when shortcodes are rendered
 #[derive(Serialize, Clone)]
 pub struct PartialPage {
+    #[serde(skip)]
+    pub name: String,
     pub title: String,
     pub description: String,
     pub date: Option<String>,
@@ -23,12 +30,295 @@ pub struct Page {
     pub template_name: String,
     #[serde(skip)]
     pub taxonomy: Option<(String, String)>,
+    /// SHA-256 of the rendered `content`, for detecting changes between builds
+    #[serde(skip)]
+    pub content_hash: String,
+    /// How this page wants the `pages` it lists sorted, if not newest first
+    #[serde(skip)]
+    pub sort_by: Option<String>,
+    #[serde(skip)]
+    pub sort_reverse: bool,
+    /// Split this page's section listing into pages of this many (see `get_section`)
+    pub paginate_by: Option<usize>,
+    /// The directory of the section this page is the index of, for pages made from `_index.md`
+    #[serde(skip)]
+    pub section: Option<String>,
     pub title: String,
     pub description: String,
+    /// `description` rendered as markdown
+    pub description_html: String,
     pub date: Option<String>,
     pub permalink: Url,
+    /// The page's URL on the site's primary copy (see `canonical_base_url`)
+    pub canonical: Url,
     pub content: String,
+    /// The frontmatter `summary` rendered as markdown if there is one, or otherwise the
+    /// content up to a `<!-- more -->` marker, if there is one of those
     pub summary: Option<String>,
+    /// `content` with the markup stripped, and whitespace collapsed
+    pub content_text: String,
+    /// Words in the page's markdown, not counting code blocks
+    pub word_count: usize,
+    /// Minutes to read the page, rounded up
+    pub reading_time: usize,
+    /// The page's headings, nested by level
+    pub toc: Vec<TocEntry>,
+    /// The sections containing this page, from the root of the site down
+    pub ancestors: Vec<SectionLink>,
     // pub key: String,
     pub taxonomies: HashMap<String, Vec<String>>,
+    /// Links to the term pages for each of this page's (declared) taxonomies
+    pub taxonomy_links: HashMap<String, Vec<TaxonomyLink>>,
+    /// Drafts are only built at all with `--drafts`
+    pub draft: bool,
+    /// Neighbouring chapters, for pages split with `split_on`
+    pub previous: Option<PageLink>,
+    pub next: Option<PageLink>,
+    /// Whether feeds carry the page's full `content`, rather than only its `description`
+    pub feed_full_content: bool,
+    pub extra: tera::Value,
+}
+
+/// A page without its (potentially large) `content` and `content_text`, for listings
+/// that only need to link to it
+#[derive(Serialize)]
+pub struct PageSummary<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub description_html: &'a str,
+    pub date: &'a Option<String>,
+    pub permalink: &'a Url,
+    pub canonical: &'a Url,
+    pub summary: &'a Option<String>,
+    pub word_count: usize,
+    pub reading_time: usize,
+    pub taxonomies: &'a HashMap<String, Vec<String>>,
+    pub taxonomy_links: &'a HashMap<String, Vec<TaxonomyLink>>,
+    pub draft: bool,
+    pub extra: &'a tera::Value,
+}
+
+impl<'a> From<&'a Page> for PageSummary<'a> {
+    fn from(page: &'a Page) -> Self {
+        Self {
+            title: &page.title,
+            description: &page.description,
+            description_html: &page.description_html,
+            date: &page.date,
+            permalink: &page.permalink,
+            canonical: &page.canonical,
+            summary: &page.summary,
+            word_count: page.word_count,
+            reading_time: page.reading_time,
+            taxonomies: &page.taxonomies,
+            taxonomy_links: &page.taxonomy_links,
+            draft: page.draft,
+            extra: &page.extra,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct TaxonomyLink {
+    pub name: String,
+    pub permalink: Url,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PageLink {
+    pub title: String,
+    pub permalink: Url,
+}
+
+impl Page {
+    pub fn hash_content(content: &str) -> String {
+        format!("{:x}", Sha256::digest(content))
+    }
+
+    /// The value of a `sort_by` key for this page: `title` (ignoring case), `date`, `permalink`,
+    /// `path` (in the order of the files, for e.g. `01-intro.md`, `02-setup.md`), or a (possibly
+    /// nested) field of `extra`, such as `extra.popularity`
+    fn sort_key(&self, key: &str) -> anyhow::Result<Option<tera::Value>> {
+        let value = match key {
+            "title" => Some(self.title.to_lowercase().into()),
+            "date" => self.date.clone().map(tera::Value::from),
+            "permalink" => Some(self.permalink.to_string().into()),
+            "path" => Some(self.name.clone().into()),
+            _ => {
+                let path = key
+                    .strip_prefix("extra.")
+                    .ok_or_else(|| anyhow!("can't sort pages by '{}'", key))?;
+                path.split('.')
+                    .try_fold(&self.extra, |value, field| value.get(field))
+                    .cloned()
+            }
+        };
+
+        Ok(value.filter(|v| !v.is_null()))
+    }
+}
+
+/// Sort pages by a `sort_by` key (see `Page::sort_key`). Pages without a value for the key
+/// always come last, and the sort is stable, so ties keep their existing order.
+pub fn sort_pages(pages: &mut [Page], key: &str, reverse: bool) -> anyhow::Result<()> {
+    let mut keyed = pages
+        .iter()
+        .map(|p| Ok((p.sort_key(key)?, p.clone())))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if reverse => compare_values(b, a),
+        (Some(a), Some(b)) => compare_values(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+
+    for (page, (_, sorted)) in pages.iter_mut().zip(keyed) {
+        *page = sorted;
+    }
+
+    Ok(())
+}
+
+/// Numbers compare numerically and strings lexically. Values of different types are
+/// ordered by type, so that mixed data at least sorts consistently.
+fn compare_values(a: &tera::Value, b: &tera::Value) -> Ordering {
+    use tera::Value::*;
+
+    let rank = |v: &tera::Value| match v {
+        Null => 0,
+        Bool(_) => 1,
+        Number(_) => 2,
+        String(_) => 3,
+        Array(_) => 4,
+        Object(_) => 5,
+    };
+
+    match (a, b) {
+        (Bool(a), Bool(b)) => a.cmp(b),
+        (Number(a), Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (String(a), String(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+#[cfg(test)]
+impl Page {
+    /// A page with just a name, title and date, and everything else empty
+    pub fn for_tests(name: &str, title: &str, date: Option<&str>) -> Self {
+        let permalink = Url::parse("https://example.com/")
+            .unwrap()
+            .join(name)
+            .unwrap();
+
+        Self {
+            name: name.to_string(),
+            output_path: PathBuf::from(name),
+            template_name: "page.html".to_string(),
+            taxonomy: None,
+            content_hash: String::new(),
+            sort_by: None,
+            sort_reverse: false,
+            paginate_by: None,
+            section: None,
+            title: title.to_string(),
+            description: String::new(),
+            description_html: String::new(),
+            date: date.map(str::to_string),
+            canonical: permalink.clone(),
+            permalink,
+            content: String::new(),
+            summary: None,
+            content_text: String::new(),
+            word_count: 0,
+            reading_time: 0,
+            toc: vec![],
+            ancestors: vec![],
+            taxonomies: HashMap::new(),
+            taxonomy_links: HashMap::new(),
+            draft: false,
+            previous: None,
+            next: None,
+            feed_full_content: false,
+            extra: tera::Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(pages: &[Page]) -> Vec<&str> {
+        pages.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_by_path_follows_file_names() {
+        let mut pages = vec![
+            Page::for_tests("docs/02-setup/index.html", "Setup", Some("2024-01-01")),
+            Page::for_tests("docs/03-usage/index.html", "Usage", None),
+            Page::for_tests("docs/01-intro/index.html", "Intro", Some("2024-02-01")),
+        ];
+
+        sort_pages(&mut pages, "path", false).unwrap();
+        assert_eq!(
+            names(&pages),
+            [
+                "docs/01-intro/index.html",
+                "docs/02-setup/index.html",
+                "docs/03-usage/index.html"
+            ]
+        );
+
+        sort_pages(&mut pages, "path", true).unwrap();
+        assert_eq!(
+            names(&pages),
+            [
+                "docs/03-usage/index.html",
+                "docs/02-setup/index.html",
+                "docs/01-intro/index.html"
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_title_ignores_case() {
+        let mut pages = vec![
+            Page::for_tests("b/index.html", "beta", None),
+            Page::for_tests("c/index.html", "Gamma", None),
+            Page::for_tests("a/index.html", "Alpha", None),
+        ];
+
+        sort_pages(&mut pages, "title", false).unwrap();
+        assert_eq!(
+            names(&pages),
+            ["a/index.html", "b/index.html", "c/index.html"]
+        );
+    }
+
+    #[test]
+    fn undated_pages_come_last_either_way() {
+        let mut pages = vec![
+            Page::for_tests("undated/index.html", "Undated", None),
+            Page::for_tests("old/index.html", "Old", Some("2023-01-01")),
+            Page::for_tests("new/index.html", "New", Some("2024-01-01")),
+        ];
+
+        sort_pages(&mut pages, "date", false).unwrap();
+        assert_eq!(
+            names(&pages),
+            ["old/index.html", "new/index.html", "undated/index.html"]
+        );
+
+        sort_pages(&mut pages, "date", true).unwrap();
+        assert_eq!(
+            names(&pages),
+            ["new/index.html", "old/index.html", "undated/index.html"]
+        );
+    }
 }
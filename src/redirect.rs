@@ -0,0 +1,22 @@
+use url::Url;
+
+/// A minimal page that immediately sends browsers (and crawlers) on to `target`
+pub fn redirect_html(target: &Url) -> String {
+    let target = tera::escape_html(target.as_str());
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Redirecting...</title>
+<link rel="canonical" href="{target}">
+<meta http-equiv="refresh" content="0; url={target}">
+</head>
+<body>
+<p>Redirecting to <a href="{target}">{target}</a></p>
+</body>
+</html>
+"#
+    )
+}
@@ -0,0 +1,51 @@
+use std::{collections::HashMap, path::Path};
+
+use tera::Tera;
+
+use crate::{highlighter::Highlighter, markdown::MarkdownRenderer, page::PartialPage, Config};
+
+/// Turns the body of a content file (everything after the frontmatter) into HTML.
+/// Content files are compiled in parallel, so renderers are shared between threads.
+pub trait ContentRenderer: Send + Sync {
+    fn render(
+        &self,
+        input: &str,
+        page: &PartialPage,
+        tera: &Tera,
+        highlighter: &Highlighter,
+        config: &Config,
+    ) -> anyhow::Result<String>;
+}
+
+/// Content renderers, chosen by the extension of the content file. Files with an
+/// extension that has no registered renderer are treated as markdown.
+pub struct Renderers {
+    default: Box<dyn ContentRenderer>,
+    by_extension: HashMap<String, Box<dyn ContentRenderer>>,
+}
+
+impl Renderers {
+    pub fn new() -> Self {
+        let mut renderers = Self {
+            default: Box::new(MarkdownRenderer),
+            by_extension: HashMap::new(),
+        };
+
+        renderers.register("md", MarkdownRenderer);
+        renderers.register("markdown", MarkdownRenderer);
+
+        renderers
+    }
+
+    pub fn register<R: ContentRenderer + 'static>(&mut self, extension: &str, renderer: R) {
+        self.by_extension
+            .insert(extension.to_string(), Box::new(renderer));
+    }
+
+    pub fn for_path(&self, path: &Path) -> &dyn ContentRenderer {
+        path.extension()
+            .and_then(|e| self.by_extension.get(e.to_string_lossy().as_ref()))
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
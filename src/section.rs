@@ -1,8 +1,42 @@
 use serde::Serialize;
+use url::Url;
 
 use crate::page::Page;
 
+/// A section as returned by `get_section`, whose pages are either full pages or summaries
 #[derive(Serialize)]
-pub struct Section {
-    pub pages: Vec<Page>,
+pub struct Section<P = Page> {
+    pub pages: Vec<P>,
+    /// The number of pages in the whole section, regardless of how many are in `pages`
+    pub total: usize,
+    pub subsections: Vec<SectionLink>,
+    /// Which page of a paginated section `pages` are, counting from 1
+    pub current_page: usize,
+    pub total_pages: usize,
+    pub previous: Option<Url>,
+    pub next: Option<Url>,
+}
+
+/// Where page `number` of a paginated section is written, relative to the output directory
+/// and without the `index.html`: the section itself for the first page, then
+/// `{section}/page/2/` and so on
+pub fn pager_path(section: &str, number: usize) -> String {
+    let section = if section.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", section)
+    };
+
+    if number <= 1 {
+        section
+    } else {
+        format!("{}page/{}/", section, number)
+    }
+}
+
+/// A section declared by an `_index.md` file, as listed in its parent's `subsections`
+#[derive(Serialize, Clone)]
+pub struct SectionLink {
+    pub title: String,
+    pub permalink: Url,
 }
@@ -0,0 +1,183 @@
+use std::{
+    fs::{self, File},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::anyhow;
+use tiny_http::{Header, Request, Response, Server};
+
+/// Polled by pages for the number of builds so far when live reloading
+const RELOAD_URL: &str = "/__reload";
+
+/// Reloads the page once the build count served at `RELOAD_URL` differs from the one the
+/// page was served with
+const RELOAD_SCRIPT: &str = r#"<script>
+setInterval(function () {
+  fetch("/__reload").then(function (r) { return r.text(); }).then(function (builds) {
+    if (builds !== "BUILDS") location.reload();
+  }).catch(function () {});
+}, 1000);
+</script>
+"#;
+
+/// Serve the built site from `output_dir` on localhost, until the process is interrupted.
+/// With a count of `builds` (in watch mode), HTML pages reload themselves after each rebuild.
+pub fn serve(output_dir: &Path, port: u16, builds: Option<Arc<AtomicUsize>>) -> anyhow::Result<()> {
+    let server = Server::http(("127.0.0.1", port)).map_err(|e| anyhow!(e))?;
+
+    println!(
+        "serving {} at http://127.0.0.1:{}/",
+        output_dir.display(),
+        port
+    );
+
+    for request in server.incoming_requests() {
+        if let Err(e) = respond(output_dir, request, builds.as_deref()) {
+            eprintln!("error serving request: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn respond(
+    output_dir: &Path,
+    request: Request,
+    builds: Option<&AtomicUsize>,
+) -> anyhow::Result<()> {
+    let builds = builds.map(|b| b.load(Ordering::SeqCst).to_string());
+
+    if let Some(builds) = &builds {
+        if request.url() == RELOAD_URL {
+            let header = Header::from_bytes("Cache-Control", "no-store")
+                .map_err(|_| anyhow!("invalid cache control"))?;
+            request.respond(Response::from_string(builds.as_str()).with_header(header))?;
+            return Ok(());
+        }
+    }
+
+    let file = resolve(output_dir, request.url()).and_then(|path| {
+        let file = File::open(&path).ok()?;
+        Some((path, file))
+    });
+
+    match file {
+        Some((path, file)) => {
+            let content_type = content_type(&path);
+            let header = Header::from_bytes("Content-Type", content_type)
+                .map_err(|_| anyhow!("invalid content type {}", content_type))?;
+
+            match builds {
+                Some(builds) if content_type.starts_with("text/html") => {
+                    let html = with_reload_script(fs::read_to_string(&path)?, &builds);
+                    request.respond(Response::from_string(html).with_header(header))?;
+                }
+                _ => request.respond(Response::from_file(file).with_header(header))?,
+            }
+        }
+        None => {
+            println!("404 {}", request.url());
+            request.respond(Response::from_string("not found").with_status_code(404))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the live reload script to a page, at the end of its body
+fn with_reload_script(html: String, builds: &str) -> String {
+    let script = RELOAD_SCRIPT.replace("BUILDS", builds);
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], script, &html[end..]),
+        None => html + &script,
+    }
+}
+
+/// Map a request URL onto a file in the output directory. Directories are served by
+/// their `index.html`, and anything that would escape the output directory is refused.
+fn resolve(output_dir: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path)?;
+
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = output_dir.join(relative);
+    if path.is_dir() {
+        Some(path.join("index.html"))
+    } else {
+        Some(path)
+    }
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = input.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_script_goes_at_the_end_of_the_body() {
+        let html = with_reload_script("<body><p>Hi</p></body>".to_string(), "3");
+        assert!(html.starts_with("<body><p>Hi</p><script>"));
+        assert!(html.ends_with("</script>\n</body>"));
+        assert!(html.contains("if (builds !== \"3\")"));
+
+        let fragment = with_reload_script("<p>Hi</p>".to_string(), "3");
+        assert!(fragment.starts_with("<p>Hi</p><script>"));
+    }
+
+    #[test]
+    fn resolve_stays_in_the_output_directory() {
+        let dir = Path::new("/site/public");
+        assert_eq!(
+            resolve(dir, "/blog/post.html?x=1"),
+            Some(dir.join("blog/post.html"))
+        );
+        assert_eq!(resolve(dir, "/a%20b.css"), Some(dir.join("a b.css")));
+        assert_eq!(resolve(dir, "/../secret"), None);
+        assert_eq!(resolve(dir, "/%2e%2e/secret"), None);
+    }
+}
@@ -1,15 +1,75 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
-use crate::page::Page;
+use crate::{
+    page::Page,
+    section::{Section, SectionLink},
+};
 
 pub struct Site {
     pub pages: HashMap<String, Page>,
+    /// Sections declared by an `_index.md`, keyed by their content-relative directory
+    pub sections: HashMap<String, SectionLink>,
+    /// For each taxonomy, the pages carrying each of its terms, newest first
+    pub taxonomies: HashMap<String, BTreeMap<String, Vec<Page>>>,
 }
 
 impl Site {
     pub fn new() -> Self {
         Self {
             pages: HashMap::new(),
+            sections: HashMap::new(),
+            taxonomies: HashMap::new(),
+        }
+    }
+
+    /// Every page under a section's (slugified) directory, in no particular order.
+    /// The index pages of sections aren't included, since they're listed as subsections.
+    pub fn section_pages<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Page> {
+        self.pages
+            .values()
+            .filter(move |page| page.section.is_none() && Path::new(&page.name).starts_with(prefix))
+    }
+
+    /// A section's pages newest first (undated pages last), with its immediate subsections
+    pub fn section(&self, prefix: &str) -> Section {
+        let mut pages = self.section_pages(prefix).cloned().collect::<Vec<_>>();
+
+        // the sort is stable, so this orders pages that tie, such as undated ones
+        pages.sort_by(|a, b| a.name.cmp(&b.name));
+        pages.sort_by(|a, b| match (&a.date, &b.date) {
+            (Some(a), Some(b)) => b.cmp(a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        let mut subsections = self
+            .sections
+            .iter()
+            .filter(|(dir, _)| {
+                Path::new(dir)
+                    .parent()
+                    .map(|p| p.to_string_lossy() == prefix)
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        subsections.sort_by_key(|(dir, _)| dir.to_string());
+
+        Section {
+            total: pages.len(),
+            pages,
+            subsections: subsections
+                .into_iter()
+                .map(|(_, link)| link.clone())
+                .collect(),
+            current_page: 1,
+            total_pages: 1,
+            previous: None,
+            next: None,
         }
     }
 }
@@ -0,0 +1,25 @@
+use crate::{feed::escape_xml, page::Page};
+
+/// The built-in sitemap, used when the site has no `sitemap.xml` template of its own.
+/// Every page is listed, including taxonomy term pages.
+pub fn sitemap(pages: &[Page]) -> String {
+    let mut output = String::new();
+    output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+
+    for page in pages {
+        output.push_str("  <url>\n");
+        output.push_str(&format!(
+            "    <loc>{}</loc>\n",
+            escape_xml(page.permalink.as_str())
+        ));
+        if let Some(date) = &page.date {
+            output.push_str(&format!("    <lastmod>{}</lastmod>\n", escape_xml(date)));
+        }
+        output.push_str("  </url>\n");
+    }
+
+    output.push_str("</urlset>\n");
+
+    output
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::anyhow;
+use tera::{ast::Node, Tera};
+
+use crate::warnings::Warnings;
+
+/// Tera only catches cycles made purely of `extends`, and recurses until the stack
+/// overflows when a template includes itself (directly or via other templates).
+/// Walk the include/extends graph up front, and report any cycle by its path.
+///
+/// Only cycles that are always followed are errors. An include inside an `if`, a `for` loop or
+/// a macro may be how a template deliberately recurses (e.g. into a nested menu), so cycles
+/// through one are warnings instead.
+pub fn check_cycles(tera: &Tera, warnings: &Warnings) -> anyhow::Result<()> {
+    // each edge records whether it's always followed when rendering
+    let mut graph: BTreeMap<&str, BTreeMap<String, bool>> = BTreeMap::new();
+
+    for name in tera.get_template_names() {
+        let template = tera.get_template(name)?;
+
+        let mut edges = BTreeMap::new();
+        if let Some(parent) = &template.parent {
+            edges.insert(parent.clone(), true);
+        }
+        collect_includes(&template.ast, true, &mut edges);
+
+        // includes of missing templates are either errors Tera reports itself,
+        // or explicitly allowed with `ignore missing`
+        edges.retain(|e, _| tera.get_template(e).is_ok());
+
+        graph.insert(name, edges);
+    }
+
+    if let Some(cycle) = find_cycles(&graph, true).first() {
+        return Err(anyhow!(
+            "circular template include detected: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    for cycle in find_cycles(&graph, false) {
+        warnings.warn(
+            "conditional template include cycle",
+            format!(
+                "{} (make sure the condition eventually stops it)",
+                cycle.join(" -> ")
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+/// Every cycle found by a depth-first walk of the graph, optionally following only the
+/// edges which are always taken
+fn find_cycles<'a>(
+    graph: &'a BTreeMap<&str, BTreeMap<String, bool>>,
+    always_only: bool,
+) -> Vec<Vec<&'a str>> {
+    let mut finished = BTreeSet::new();
+    let mut cycles = vec![];
+
+    for name in graph.keys() {
+        let mut path = vec![];
+        visit(
+            name,
+            graph,
+            always_only,
+            &mut path,
+            &mut finished,
+            &mut cycles,
+        );
+    }
+
+    cycles
+}
+
+fn visit<'a>(
+    name: &'a str,
+    graph: &'a BTreeMap<&str, BTreeMap<String, bool>>,
+    always_only: bool,
+    path: &mut Vec<&'a str>,
+    finished: &mut BTreeSet<&'a str>,
+    cycles: &mut Vec<Vec<&'a str>>,
+) {
+    if finished.contains(name) {
+        return;
+    }
+
+    if let Some(start) = path.iter().position(|n| *n == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name);
+        cycles.push(cycle);
+        return;
+    }
+
+    path.push(name);
+    for (next, always) in graph.get(name).into_iter().flatten() {
+        if *always || !always_only {
+            visit(next, graph, always_only, path, finished, cycles);
+        }
+    }
+    path.pop();
+
+    finished.insert(name);
+}
+
+fn collect_includes(nodes: &[Node], always: bool, edges: &mut BTreeMap<String, bool>) {
+    for node in nodes {
+        match node {
+            Node::Include(_, names, _) => {
+                for name in names {
+                    *edges.entry(name.clone()).or_default() |= always;
+                }
+            }
+            Node::Block(_, block, _) => collect_includes(&block.body, always, edges),
+            Node::MacroDefinition(_, definition, _) => {
+                collect_includes(&definition.body, false, edges)
+            }
+            Node::FilterSection(_, section, _) => collect_includes(&section.body, always, edges),
+            Node::Forloop(_, forloop, _) => {
+                collect_includes(&forloop.body, false, edges);
+                if let Some(body) = &forloop.empty_body {
+                    collect_includes(body, false, edges);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, _, body) in &condition.conditions {
+                    collect_includes(body, false, edges);
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    collect_includes(body, false, edges);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(templates: &[(&str, &str)]) -> (anyhow::Result<()>, Warnings) {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(templates.iter().copied()).unwrap();
+        let warnings = Warnings::default();
+        (check_cycles(&tera, &warnings), warnings)
+    }
+
+    #[test]
+    fn unconditional_include_cycle() {
+        let (result, _) = check(&[
+            ("a.html", "{% include \"b.html\" %}"),
+            ("b.html", "{% include \"a.html\" %}"),
+        ]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "circular template include detected: a.html -> b.html -> a.html"
+        );
+    }
+
+    #[test]
+    fn include_cycle_through_extends() {
+        let (result, _) = check(&[
+            ("base.html", "{% include \"page.html\" %}"),
+            ("page.html", "{% extends \"base.html\" %}"),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn conditional_include_cycle() {
+        let (result, warnings) = check(&[(
+            "menu.html",
+            "{% for item in items %}{% if item.children %}{% include \"menu.html\" %}{% endif %}{% endfor %}",
+        )]);
+        assert!(result.is_ok());
+        assert!(!warnings.is_empty());
+    }
+
+    #[test]
+    fn no_cycle() {
+        let (result, warnings) = check(&[
+            (
+                "base.html",
+                "{% include \"nav.html\" %}{% include \"nav.html\" %}",
+            ),
+            ("nav.html", ""),
+        ]);
+        assert!(result.is_ok());
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{markdown::strip_html, slugify, Config};
+
+/// One heading of a page, with the headings below it that are nested inside it
+#[derive(Serialize, Clone)]
+pub struct TocEntry {
+    pub level: usize,
+    pub title: String,
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Give every heading in rendered page content an `id` (unless it already has one), derived
+/// from its text and kept unique within the page, and collect the headings into a table of
+/// contents. Ids are applied to the final HTML, so headings from every markdown block and
+/// shortcode share one set of ids.
+///
+/// With `heading_anchors`, headings from `anchor_min_level` down also start with an empty
+/// `<a class="anchor">` linking to themselves, for themes to style.
+///
+/// A paragraph of nothing but `[[toc]]` is replaced with the table of contents, as nested
+/// lists of links.
+pub fn heading_ids(content: &str, config: &Config) -> (String, Vec<TocEntry>) {
+    let mut output = String::with_capacity(content.len());
+    let mut toc = vec![];
+    let mut ids = HashSet::new();
+    let mut rest = content;
+
+    while let Some((start, level)) = find_heading(rest) {
+        let close = format!("</h{}>", level);
+
+        let open_end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let inner_end = match rest[open_end..].find(&close) {
+            Some(end) => open_end + end,
+            None => break,
+        };
+
+        let attributes = &rest[start + 3..open_end];
+        let inner = &rest[open_end + 1..inner_end];
+        let title = strip_html(inner);
+
+        output.push_str(&rest[..start]);
+
+        let id = match existing_id(attributes) {
+            Some(id) => {
+                output.push_str(&rest[start..open_end + 1]);
+                id.to_string()
+            }
+            None => {
+                let id = unique_id(&title, &ids);
+                output.push_str(&format!("<h{} id=\"{}\"{}>", level, id, attributes));
+                id
+            }
+        };
+        ids.insert(id.clone());
+
+        if config.heading_anchors && level >= config.anchor_min_level {
+            output.push_str(&format!("<a class=\"anchor\" href=\"#{}\"></a>", id));
+        }
+
+        output.push_str(inner);
+        output.push_str(&close);
+        rest = &rest[inner_end + close.len()..];
+
+        insert(
+            &mut toc,
+            TocEntry {
+                level,
+                title,
+                id,
+                children: vec![],
+            },
+        );
+    }
+
+    output.push_str(rest);
+
+    if output.contains(TOC_MARKER) {
+        let mut list = String::new();
+        push_list(&mut list, &toc);
+        output = output.replace(TOC_MARKER, &list);
+    }
+
+    (output, toc)
+}
+
+/// Written on a line of its own to place the table of contents
+pub const TOC: &str = "[[toc]]";
+
+/// `[[toc]]` on a line of its own, as markdown renders it
+const TOC_MARKER: &str = "<p>[[toc]]</p>\n";
+
+fn push_list(output: &mut String, entries: &[TocEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+
+    output.push_str("<ul class=\"toc\">");
+    for entry in entries {
+        output.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            entry.id,
+            tera::escape_html(&entry.title)
+        ));
+        push_list(output, &entry.children);
+        output.push_str("</li>");
+    }
+    output.push_str("</ul>\n");
+}
+
+/// The offset and level of the next `<h1>`...`<h6>` opening tag
+fn find_heading(html: &str) -> Option<(usize, usize)> {
+    html.match_indices("<h").find_map(|(start, _)| {
+        let bytes = html.as_bytes();
+        let level = *bytes.get(start + 2)?;
+        let next = *bytes.get(start + 3)?;
+
+        let is_heading =
+            (b'1'..=b'6').contains(&level) && (next == b'>' || next.is_ascii_whitespace());
+        is_heading.then(|| (start, (level - b'0') as usize))
+    })
+}
+
+fn existing_id(attributes: &str) -> Option<&str> {
+    let start = attributes.find(" id=\"")? + 5;
+    let end = start + attributes[start..].find('"')?;
+    Some(&attributes[start..end])
+}
+
+/// A slug of the heading's text, numbered `-1`, `-2` and so on if it's already taken
+fn unique_id(title: &str, ids: &HashSet<String>) -> String {
+    let mut base = slugify(&title.replace('/', " "));
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+
+    let mut id = base.clone();
+    let mut n = 0;
+    while ids.contains(&id) {
+        n += 1;
+        id = format!("{}-{}", base, n);
+    }
+
+    id
+}
+
+/// Add a heading to the table of contents, nested inside the last heading above its level
+fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+    match entries.last_mut() {
+        Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+
+    #[test]
+    fn toc_marker_is_replaced() {
+        let content = "<p>[[toc]]</p>\n<h1>One</h1>\n<h2>Two &amp; a half</h2>\n<h1>Three</h1>\n\
+            <p>[[toc]]</p>\n";
+        let (output, _) = heading_ids(content, &test_config(""));
+
+        let list = "<ul class=\"toc\"><li><a href=\"#one\">One</a><ul class=\"toc\">\
+            <li><a href=\"#two-a-half\">Two &amp; a half</a></li></ul>\n</li>\
+            <li><a href=\"#three\">Three</a></li></ul>\n";
+        assert_eq!(
+            output,
+            format!(
+                "{}<h1 id=\"one\">One</h1>\n<h2 id=\"two-a-half\">Two &amp; a half</h2>\n\
+                <h1 id=\"three\">Three</h1>\n{}",
+                list, list
+            )
+        );
+    }
+
+    #[test]
+    fn content_without_marker_is_unchanged() {
+        let content = "<p>Just text, with [[toc]] inline.</p>\n";
+        assert_eq!(heading_ids(content, &test_config("")).0, content);
+    }
+}
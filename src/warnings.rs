@@ -0,0 +1,41 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+};
+
+/// Collects non-fatal problems found during the build, so that they can be
+/// reported together at the end rather than scattered through the log.
+#[derive(Clone, Default)]
+pub struct Warnings {
+    entries: Arc<Mutex<BTreeMap<&'static str, BTreeSet<String>>>>,
+}
+
+impl Warnings {
+    pub fn warn<S: Into<String>>(&self, category: &'static str, message: S) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.entry(category).or_default().insert(message.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Print all warnings, grouped by category
+    pub fn report(&self) {
+        let entries = self.entries.lock().unwrap();
+
+        if entries.is_empty() {
+            return;
+        }
+
+        let count = entries.values().map(|m| m.len()).sum::<usize>();
+        eprintln!("{} warning(s):", count);
+
+        for (category, messages) in entries.iter() {
+            eprintln!("  {}:", category);
+            for message in messages {
+                eprintln!("    {}", message);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::functions::load_data::DataFiles;
+
+/// Everything in the site besides its content and data files that affects the build
+const WATCHED: [&str; 6] = [
+    "config.toml",
+    "templates",
+    "static",
+    "syntaxes",
+    "highlight_themes",
+    "themes",
+];
+
+/// How long the site has to stay unchanged before rebuilding, so that saving several
+/// files at once only triggers a single build
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watch the site and its `content` directory for changes, calling `rebuild` with the
+/// changed files after each burst of changes settles down. The directories of `data_files`
+/// are watched as well, as builds add to them. Blocks until the process is interrupted.
+pub fn watch(
+    home: &Path,
+    content: &Path,
+    data_files: &DataFiles,
+    mut rebuild: impl FnMut(&[&Path]),
+) -> anyhow::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    let roots = WATCHED
+        .iter()
+        .map(Path::new)
+        .chain([content])
+        .map(|name| home.join(name))
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+    for path in &roots {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+
+    // directories rather than files, as editors often save by replacing the file, and so
+    // that new files matching a `load_data` glob are noticed too
+    let mut data_dirs = BTreeSet::new();
+    let mut watch_data_files = |watcher: &mut notify::RecommendedWatcher| -> anyhow::Result<()> {
+        let files = data_files.lock().unwrap();
+        for dir in files.iter().filter_map(|file| file.parent()) {
+            if data_dirs.insert(dir.to_path_buf()) {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+        Ok(())
+    };
+    watch_data_files(&mut watcher)?;
+
+    println!("watching {} for changes", home.display());
+
+    // a data directory may be shared with other files, such as the output directory when
+    // data files are at the root of the site, so only data files count there
+    let relevant = |path: &PathBuf| {
+        roots.iter().any(|root| path.starts_with(root))
+            || matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("toml" | "json")
+            )
+    };
+
+    // the build reads every file, so only changes count, or each build would trigger another
+    let changes = |event: notify::Result<Event>| match event {
+        Ok(event)
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) =>
+        {
+            event.paths.into_iter().filter(relevant).collect()
+        }
+        Ok(_) => vec![],
+        Err(e) => {
+            eprintln!("error watching for changes: {}", e);
+            vec![]
+        }
+    };
+
+    while let Ok(event) = receiver.recv() {
+        let mut changed: Vec<PathBuf> = changes(event);
+        if changed.is_empty() {
+            continue;
+        }
+
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            changed.extend(changes(event));
+        }
+
+        changed.sort();
+        changed.dedup();
+
+        rebuild(&changed.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+        watch_data_files(&mut watcher)?;
+    }
+
+    Ok(())
+}